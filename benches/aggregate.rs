@@ -0,0 +1,80 @@
+//! Merge and roll-up of the worker results at the end of a walk.
+//!
+//! Directories of a synthetic tree are spread over the workers like a walk
+//! would, then merged and aggregated. The number of directories can be set
+//! with `FDU_BENCH_DIRS`.
+//!
+//! ```bash
+//! FDU_BENCH_DIRS=1000000 cargo bench --bench aggregate
+//! ```
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use fdu::core::aggregate::{self, DirLevels, DirStats};
+use std::path::PathBuf;
+
+const THREADS: [usize; 4] = [1, 4, 16, 32];
+
+/// Subdirectories per directory of the synthetic tree
+const BREADTH: usize = 10;
+
+/// The per-worker directories of a tree of `dirs` directories, each holding
+/// `BREADTH` subdirectories, handed out to `workers` in turn in a shuffled
+/// order like a walk would visit them
+fn worker_levels(dirs: usize, workers: usize) -> Vec<DirLevels> {
+    let mut levels: Vec<DirLevels> = (0..workers).map(|_| DirLevels::default()).collect();
+    let mut queue = vec![(PathBuf::from("/bench"), 0)];
+    let mut next = 0;
+    while next < queue.len() && queue.len() < dirs {
+        let (path, depth) = queue[next].clone();
+        for i in 0..BREADTH.min(dirs - queue.len()) {
+            queue.push((path.join(format!("dir{i}")), depth + 1));
+        }
+        next += 1;
+    }
+    // Fisher-Yates with a fixed xorshift seed, every run gets the same order
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    for i in (1..queue.len()).rev() {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        queue.swap(i, (seed % (i as u64 + 1)) as usize);
+    }
+    for (i, (path, depth)) in queue.into_iter().enumerate() {
+        let mut stats = DirStats::new(depth, 0);
+        stats.blocks = 8;
+        stats.apparent_size = 4096;
+        levels[i % workers].insert(path, stats);
+    }
+    levels
+}
+
+fn merge(c: &mut Criterion) {
+    let dirs = std::env::var("FDU_BENCH_DIRS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(200_000);
+
+    let mut group = c.benchmark_group(format!("aggregate/{dirs}"));
+    // Reported as directories per second
+    group.throughput(Throughput::Elements(dirs as u64));
+    group.sample_size(10);
+    for threads in THREADS {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &threads| {
+            b.iter_batched(
+                || worker_levels(dirs, threads),
+                |levels| {
+                    let mut merged = DirLevels::default();
+                    for worker in levels {
+                        merged.merge(worker);
+                    }
+                    aggregate::aggregate(merged, Vec::new(), threads)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, merge);
+criterion_main!(benches);
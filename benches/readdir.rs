@@ -0,0 +1,83 @@
+//! Reading a single large directory: batches of entries with `getdents64`
+//! into a reused buffer, like the job processor does on Linux, against
+//! the `std::fs::read_dir` fallback of the other platforms.
+//!
+//! The number of entries can be set with `FDU_BENCH_ENTRIES`.
+//!
+//! ```bash
+//! FDU_BENCH_ENTRIES=100000 cargo bench --bench readdir
+//! ```
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use rustix::fs::{Mode, OFlags, RawDir};
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Bytes of entries read per `getdents64` call, as in the job processor
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const DIRENT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Create `entries` empty files in `dir`
+fn build_dir(dir: &Path, entries: usize) {
+    for i in 0..entries {
+        File::create(dir.join(format!("file-{i:07}"))).expect("create fixture file");
+    }
+}
+
+/// Read `dir` with `getdents64`, returning the number of entries. The
+/// type comes with each entry.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_getdents(dir: &Path, buffer: &mut Vec<u8>) -> usize {
+    let fd = rustix::fs::open(dir, OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC, Mode::empty())
+        .expect("open fixture directory");
+    let mut entries = RawDir::new(&fd, buffer.spare_capacity_mut());
+    let mut count = 0;
+    while let Some(entry) = entries.next() {
+        let entry = entry.expect("read fixture directory");
+        let name = entry.file_name().to_bytes();
+        if name != b"." && name != b".." {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Read `dir` with `std::fs::read_dir`, returning the number of entries.
+/// The type of each entry is asked for, the walk needs it too.
+fn read_std(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .expect("open fixture directory")
+        .map(|entry| entry.expect("read fixture directory"))
+        .filter(|entry| entry.file_type().is_ok())
+        .count()
+}
+
+fn readdir(c: &mut Criterion) {
+    let entries = std::env::var("FDU_BENCH_ENTRIES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(500_000);
+
+    let fixture = tempfile::tempdir().expect("create fixture directory");
+    build_dir(fixture.path(), entries);
+
+    let mut group = c.benchmark_group(format!("readdir/{entries}"));
+    // Reported as entries per second
+    group.throughput(Throughput::Elements(entries as u64));
+    group.sample_size(10);
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let mut buffer = Vec::with_capacity(DIRENT_BUFFER_SIZE);
+        assert_eq!(read_getdents(fixture.path(), &mut buffer), entries);
+        group.bench_function("getdents64", |b| b.iter(|| read_getdents(fixture.path(), &mut buffer)));
+    }
+
+    assert_eq!(read_std(fixture.path()), entries);
+    group.bench_function("read_dir", |b| b.iter(|| read_std(fixture.path())));
+    group.finish();
+}
+
+criterion_group!(benches, readdir);
+criterion_main!(benches);
@@ -0,0 +1,126 @@
+//! Walker throughput on a synthetic tree.
+//!
+//! The tree shape can be set with environment variables:
+//! `FDU_BENCH_BREADTH` subdirectories per directory, `FDU_BENCH_DEPTH`
+//! levels of directories and `FDU_BENCH_FILES` files per directory.
+//!
+//! ```bash
+//! FDU_BENCH_BREADTH=8 FDU_BENCH_DEPTH=4 cargo bench --bench walk
+//! ```
+//!
+//! The `idle` group measures the CPU time of a walk instead of its
+//! wall-clock time, for the idle backoff of the workers.
+
+use clap::Parser;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use fdu::cli::Cli;
+use fdu::config::Config;
+#[cfg(unix)]
+use fdu::config::IdleBackoff;
+use fdu::core::walker::{Multithreaded, Walker};
+use std::fs;
+use std::path::Path;
+#[cfg(unix)]
+use std::time::Duration;
+
+const THREADS: [usize; 4] = [1, 4, 16, 32];
+
+/// Read a tree dimension from the environment
+fn dimension(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Build a tree of `depth` levels below `dir`, each directory holding
+/// `breadth` subdirectories and `files` small files. Returns the number
+/// of files created.
+fn build_tree(dir: &Path, breadth: usize, depth: usize, files: usize) -> u64 {
+    let mut created = 0;
+    for i in 0..files {
+        fs::write(dir.join(format!("file{i}")), [0u8; 100]).expect("create fixture file");
+        created += 1;
+    }
+    if depth > 0 {
+        for i in 0..breadth {
+            let subdir = dir.join(format!("dir{i}"));
+            fs::create_dir(&subdir).expect("create fixture directory");
+            created += build_tree(&subdir, breadth, depth - 1, files);
+        }
+    }
+    created
+}
+
+fn walk(c: &mut Criterion) {
+    let breadth = dimension("FDU_BENCH_BREADTH", 6);
+    let depth = dimension("FDU_BENCH_DEPTH", 4);
+    let files = dimension("FDU_BENCH_FILES", 10);
+
+    let tree = tempfile::tempdir().expect("create fixture root");
+    let total_files = build_tree(tree.path(), breadth, depth, files);
+    let root = tree.path().to_string_lossy().into_owned();
+
+    let mut group = c.benchmark_group(format!("walk/{breadth}x{depth}x{files}"));
+    // Reported as files per second
+    group.throughput(Throughput::Elements(total_files));
+    for threads in THREADS {
+        let cli = Cli::parse_from(["fdu", "-j", &threads.to_string(), &root]);
+        let config = Config::from_cli(&cli).expect("valid benchmark config");
+        let walker = Multithreaded::new(&config);
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &config.paths, |b, roots| {
+            b.iter(|| walker.walk(roots).expect("walk fixture tree"))
+        });
+    }
+    group.finish();
+}
+
+/// CPU time used by the process so far, all threads together
+#[cfg(unix)]
+fn cpu_time() -> Duration {
+    let time = rustix::time::clock_gettime(rustix::time::ClockId::ProcessCPUTime);
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// CPU time of a walk on 32 threads, with the default idle backoff and
+/// with workers that never stop spinning and yielding, as they did
+/// before they could park
+#[cfg(unix)]
+fn idle(c: &mut Criterion) {
+    let breadth = dimension("FDU_BENCH_BREADTH", 6);
+    let depth = dimension("FDU_BENCH_DEPTH", 4);
+    let files = dimension("FDU_BENCH_FILES", 10);
+
+    let tree = tempfile::tempdir().expect("create fixture root");
+    build_tree(tree.path(), breadth, depth, files);
+    let root = tree.path().to_string_lossy().into_owned();
+
+    let spinning = IdleBackoff {
+        spin_cycles: 10,
+        yield_cycles: usize::MAX,
+        park_timeout: Duration::ZERO,
+    };
+    let mut group = c.benchmark_group(format!("idle/{breadth}x{depth}x{files}"));
+    for (name, backoff) in [("default", IdleBackoff::default()), ("spinning", spinning)] {
+        let cli = Cli::parse_from(["fdu", "-j", "32", &root]);
+        let mut config = Config::from_cli(&cli).expect("valid benchmark config");
+        config.performance_config.idle = backoff;
+        let walker = Multithreaded::new(&config);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &config.paths, |b, roots| {
+            b.iter_custom(|iters| {
+                let start = cpu_time();
+                for _ in 0..iters {
+                    walker.walk(roots).expect("walk fixture tree");
+                }
+                cpu_time() - start
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(unix)]
+criterion_group!(benches, walk, idle);
+#[cfg(not(unix))]
+criterion_group!(benches, walk);
+criterion_main!(benches);
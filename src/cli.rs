@@ -6,10 +6,14 @@ use std::path::PathBuf;
 #[command(about = "Crazy fast disk usage analyzer", long_about = None)]
 #[command(version)]
 pub struct Cli {
-    /// Paths to analyze
-    #[arg(value_name = "PATH", default_values = ["."])]
+    /// Paths to analyze [default: .]
+    #[arg(value_name = "PATH")]
     pub paths: Vec<PathBuf>,
 
+    /// Read NUL-separated paths to analyze from FILE, or stdin when FILE is -
+    #[arg(long = "files0-from", value_name = "FILE")]
+    pub files0_from: Option<PathBuf>,
+
     /// Display all files and directories
     #[arg(short = 'a', long = "all")]
     pub all: bool,
@@ -22,9 +26,36 @@ pub struct Cli {
     #[arg(short = 'f', long, conflicts_with = "dirs_only")]
     pub files_only: bool,
 
-    /// Size display format
-    #[arg(short = 'F', long, value_enum, default_value = "human")]
-    pub format: SizeFormat,
+    /// Size display format [default: human]
+    #[arg(short = 'F', long, value_enum)]
+    pub format: Option<SizeFormat>,
+
+    /// Add a column with each entry's share of its parent directory, or of the grand total
+    #[arg(
+        long = "percent",
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "parent",
+        require_equals = true,
+        value_name = "OF"
+    )]
+    pub percent: Option<PercentOf>,
+
+    /// Add a column with the number of files below each entry
+    #[arg(long = "count", conflicts_with_all = ["output", "porcelain"])]
+    pub count: bool,
+
+    /// Human-readable sizes, same as --format human (the default)
+    #[arg(long = "human-readable", conflicts_with = "format")]
+    pub human_readable: bool,
+
+    /// Group the digits of plain numbers with the locale's thousands separator
+    #[arg(long = "thousands")]
+    pub thousands: bool,
+
+    /// Display the number of inodes instead of the size
+    #[arg(long = "inodes")]
+    pub inodes: bool,
 
     /// Display apparent size
     #[arg(long = "apparent-size")]
@@ -38,10 +69,27 @@ pub struct Cli {
     #[arg(short = 'c', long = "total")]
     pub total: bool,
 
-    /// Maximum depth
+    /// Leave out the closing "Total size" line of the default output
+    #[arg(long = "no-total-line")]
+    pub no_total_line: bool,
+
+    /// Color sizes from green to red, relative to the largest entry
+    /// displayed (with --color, on a terminal)
+    #[arg(long = "color-by-size", conflicts_with_all = ["output", "porcelain"])]
+    pub color_by_size: bool,
+
+    /// Display entries up to depth N, 0 for only the input paths
     #[arg(short = 'L', long = "max-depth", value_name = "N")]
     pub max_depth: Option<usize>,
 
+    /// Display only the entries at depth N, with the totals of their whole subtree
+    #[arg(long = "depth-summary", value_name = "N", conflicts_with_all = ["max_depth", "summarize"])]
+    pub depth_summary: Option<usize>,
+
+    /// Do not walk directories deeper than N, their contents are not counted
+    #[arg(long = "prune-depth", value_name = "N")]
+    pub prune_depth: Option<usize>,
+
     /// Minimum depth
     #[arg(long = "min-depth", value_name = "N")]
     pub min_depth: Option<usize>,
@@ -50,7 +98,7 @@ pub struct Cli {
     #[arg(short = 's', long = "summarize")]
     pub summarize: bool,
 
-    /// Sort by field
+    /// Sort by field, by name when not given
     #[arg(short = 'S', long, value_enum)]
     pub sort: Option<SortField>,
 
@@ -58,6 +106,71 @@ pub struct Cli {
     #[arg(short = 'r', long)]
     pub reverse: bool,
 
+    /// Display the number and total size of files per size range
+    #[arg(long = "histogram", conflicts_with = "by_extension")]
+    pub histogram: bool,
+
+    /// Display the number and total size of files per file extension
+    #[arg(long = "by-extension")]
+    pub by_extension: bool,
+
+    /// Display sparse files, apparent size against allocated size
+    #[arg(long = "sparse", conflicts_with_all = ["histogram", "by_extension"])]
+    pub sparse: bool,
+
+    /// Display the number and total size of files per mounted filesystem
+    #[arg(
+        long = "by-mount",
+        conflicts_with_all = ["histogram", "by_extension", "sparse", "one_file_system"]
+    )]
+    pub by_mount: bool,
+
+    /// List empty directories and zero-byte files
+    #[arg(
+        long = "empty",
+        conflicts_with_all = ["histogram", "by_extension", "sparse", "by_mount"]
+    )]
+    pub empty: bool,
+
+    /// List files of at least SIZE (default 1) with identical content, and the space they waste
+    #[arg(
+        long = "duplicates",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        require_equals = true,
+        value_name = "SIZE",
+        conflicts_with_all = ["histogram", "by_extension", "sparse", "by_mount", "empty"]
+    )]
+    pub duplicates: Option<String>,
+
+    /// Display the number and total size of files per content type, told
+    /// from their first BYTES (default 8K)
+    #[arg(
+        long = "by-type",
+        num_args = 0..=1,
+        default_missing_value = "8K",
+        require_equals = true,
+        value_name = "BYTES",
+        conflicts_with_all = ["histogram", "by_extension", "sparse", "by_mount", "empty", "duplicates"]
+    )]
+    pub by_type: Option<String>,
+
+    /// Only tell whether each path takes more than SIZE, stopping the scan
+    /// as soon as one does. Exits with 1 when one does
+    #[arg(
+        long = "exceeds",
+        value_name = "SIZE",
+        conflicts_with_all = [
+            "output", "porcelain", "inodes", "sample", "watch", "histogram", "by_extension",
+            "sparse", "by_mount", "empty", "duplicates", "by_type"
+        ]
+    )]
+    pub exceeds: Option<String>,
+
+    /// Display only the N largest entries, largest first
+    #[arg(long = "top", value_name = "N", conflicts_with_all = ["sort", "reverse"])]
+    pub top: Option<usize>,
+
     /// Include patterns
     #[arg(long = "include", value_name = "PATTERN")]
     pub include_patterns: Vec<String>,
@@ -66,6 +179,22 @@ pub struct Cli {
     #[arg(long = "exclude", value_name = "PATTERN")]
     pub exclude_patterns: Vec<String>,
 
+    /// Read exclude patterns from FILE, one regex per line
+    #[arg(long = "exclude-from", value_name = "FILE")]
+    pub exclude_from: Vec<PathBuf>,
+
+    /// Include glob patterns, like `*.rs`
+    #[arg(long = "iglob", value_name = "GLOB")]
+    pub include_globs: Vec<String>,
+
+    /// Exclude glob patterns, like `**/node_modules`
+    #[arg(long = "eglob", value_name = "GLOB")]
+    pub exclude_globs: Vec<String>,
+
+    /// Skip entries ignored by .gitignore files in the scanned directories
+    #[arg(long = "gitignore")]
+    pub gitignore: bool,
+
     /// Exclude from file
     // pub exclude_from: Option<PathBuf>,
 
@@ -73,10 +202,59 @@ pub struct Cli {
     #[arg(short = 't', long = "threshold", value_name = "SIZE")]
     pub threshold: Option<String>,
 
+    /// Hide directories holding fewer than N files in their subtree
+    #[arg(long = "min-files", value_name = "N")]
+    pub min_files: Option<u64>,
+
+    /// Count only files of at least SIZE
+    #[arg(long = "min-size", value_name = "SIZE")]
+    pub min_size: Option<String>,
+
+    /// Count only files of at most SIZE
+    #[arg(long = "max-size", value_name = "SIZE")]
+    pub max_size: Option<String>,
+
+    /// Count only files modified after TIME, an age like 7d or a date
+    #[arg(long = "newer-than", value_name = "TIME")]
+    pub newer_than: Option<String>,
+
+    /// Count only files modified before TIME, an age like 7d or a date
+    #[arg(long = "older-than", value_name = "TIME")]
+    pub older_than: Option<String>,
+
+    /// Count only files owned by the user with this uid
+    #[arg(long = "uid", value_name = "N", conflicts_with = "user")]
+    pub uid: Option<u32>,
+
+    /// Count only files owned by this user
+    #[arg(long = "user", value_name = "NAME")]
+    pub user: Option<String>,
+
+    /// Count only files owned by the group with this gid
+    #[arg(long = "gid", value_name = "N", conflicts_with = "group")]
+    pub gid: Option<u32>,
+
+    /// Count only files owned by this group
+    #[arg(long = "group", value_name = "NAME")]
+    pub group: Option<String>,
+
     /// Count hard links
     #[arg(short = 'l', long = "count-links")]
     pub count_links: bool,
 
+    /// Count block and character devices, FIFOs and sockets
+    #[arg(long = "include-special", overrides_with = "exclude_special")]
+    pub include_special: bool,
+
+    /// Count only regular files: not the blocks of directories
+    /// themselves, nor symlinks, devices, FIFOs or sockets
+    #[arg(long = "regular-only", conflicts_with = "include_special")]
+    pub regular_only: bool,
+
+    /// Skip block and character devices, FIFOs and sockets (default)
+    #[arg(long = "exclude-special", overrides_with = "include_special")]
+    pub exclude_special: bool,
+
     /// Dereference (follow) symlinks
     #[arg(short = 'H', long = "dereference")]
     pub dereference: bool,
@@ -85,10 +263,42 @@ pub struct Cli {
     #[arg(short = 'x', long = "one-file-system")]
     pub one_file_system: bool,
 
-    /// Number of threads
+    /// Number of threads, 0 to pick from the kind of storage
     #[arg(short = 'j', long = "jobs", default_value = "32")]
     pub threads: usize,
 
+    /// Walk each device with N threads of its own instead of sharing --jobs
+    /// threads across devices
+    #[arg(long = "threads-per-device", value_name = "N")]
+    pub threads_per_device: Option<usize>,
+
+    /// Stop the scan after DURATION (like 30s, 5m, 2h) and report partial totals
+    #[arg(long = "max-time", value_name = "DURATION")]
+    pub max_time: Option<String>,
+
+    /// Stop the scan once more than N files and directories were
+    /// processed, naming the directory that went over
+    #[arg(long = "max-entries", value_name = "N")]
+    pub max_entries: Option<u64>,
+
+    /// Estimate sizes from a fraction RATE of the files (like 0.1 or 10%),
+    /// the others are not stat'ed
+    #[arg(
+        long = "sample",
+        value_name = "RATE",
+        conflicts_with_all = ["inodes", "porcelain", "histogram", "by_extension", "sparse", "by_mount", "empty", "duplicates", "by_type"]
+    )]
+    pub sample: Option<String>,
+
+    /// Maximum number of jobs a thread takes from the shared queue at once
+    #[arg(long = "batch-size", value_name = "N", default_value = "32")]
+    pub batch_size: usize,
+
+    /// Jobs a thread keeps in its own queue, the excess goes to the shared
+    /// queue for the other threads
+    #[arg(long = "local-queue-cap", value_name = "N", default_value = "1000")]
+    pub local_queue_cap: usize,
+
     /// Cache size in MB (for hard links)
     #[arg(long = "cache-size", default_value = "100")]
     pub cache_size_mb: usize,
@@ -101,6 +311,18 @@ pub struct Cli {
     #[arg(long = "buffer-errors")]
     pub buffer_errors: bool,
 
+    /// Fail when an input path does not exist, instead of skipping it
+    #[arg(long = "strict")]
+    pub strict: bool,
+
+    /// What to do when an entry cannot be read
+    #[arg(long = "on-error", value_enum, default_value = "skip", value_name = "POLICY")]
+    pub on_error: ErrorPolicy,
+
+    /// Exit with status 0 even when entries were skipped on errors
+    #[arg(long = "ignore-errors")]
+    pub ignore_errors: bool,
+
     /// Exclude cache directories
     #[arg(long = "exclude-caches")]
     pub exclude_caches: bool,
@@ -109,24 +331,188 @@ pub struct Cli {
     #[arg(short = 'o', long, value_enum)]
     pub output: Option<OutputFormat>,
 
-    /// Show modification time
-    #[arg(long = "time")]
-    pub show_time: bool,
+    /// Same as --output porcelain
+    #[arg(
+        long = "porcelain",
+        conflicts_with_all = ["output", "histogram", "by_extension", "sparse", "by_mount", "empty", "duplicates", "by_type"]
+    )]
+    pub porcelain: bool,
+
+    /// Write the results to FILE instead of stdout
+    #[arg(short = 'O', long = "output-file", value_name = "FILE")]
+    pub output_file: Option<PathBuf>,
+
+    /// Also write every skipped entry to FILE, one JSON object per line
+    #[arg(long = "error-json", value_name = "FILE")]
+    pub error_json: Option<PathBuf>,
+
+    /// Database file for the SQLite output
+    #[arg(long = "db", value_name = "FILE")]
+    pub db: Option<PathBuf>,
+
+    /// Save the scan to FILE, to compare with a later one with --diff
+    #[arg(long = "save", value_name = "FILE")]
+    pub save: Option<PathBuf>,
+
+    /// Compare two scans saved with --save instead of scanning
+    #[arg(long = "diff", num_args = 2, value_names = ["OLD", "NEW"], conflicts_with = "save")]
+    pub diff: Option<Vec<PathBuf>>,
+
+    /// Keep watching the paths after the scan, and scan again whenever
+    /// files change
+    #[arg(long = "watch", conflicts_with_all = ["diff", "dry_run"])]
+    pub watch: bool,
+
+    /// End each output line with NUL instead of newline
+    #[arg(short = '0', long = "null")]
+    pub null: bool,
+
+    /// Print the resolved roots and settings, then exit without scanning
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Print scan duration, throughput and per-thread scheduling to stderr
+    #[arg(long = "stats")]
+    pub stats: bool,
+
+    /// How paths are displayed: given, relative, absolute or name
+    #[arg(long = "path-style", value_enum, default_value = "given", value_name = "STYLE")]
+    pub path_style: PathStyle,
+
+    /// Show the most recent time in each entry: mtime (the default),
+    /// atime or ctime. Sorting by time uses the same one
+    #[arg(
+        long = "time",
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "mtime",
+        require_equals = true,
+        value_name = "WORD"
+    )]
+    pub time: Option<TimeField>,
+
+    /// Time format with --time: full-iso, long-iso (the default), iso or
+    /// +FORMAT (strftime)
+    #[arg(long = "time-style", value_name = "STYLE")]
+    pub time_style: Option<String>,
+
+    /// Read default options from FILE instead of ~/.config/fdu/config.toml
+    #[arg(long = "config", value_name = "FILE", conflicts_with = "no_config")]
+    pub config: Option<PathBuf>,
+
+    /// Do not read default options from a config file
+    #[arg(long = "no-config")]
+    pub no_config: bool,
+
+    /// Only log errors, overriding FDU_LOG
+    #[arg(short = 'q', long = "quiet")]
+    pub quiet: bool,
+
+    /// Include the module that logged in each log line
+    #[arg(long = "log-target")]
+    pub log_target: bool,
+
+    /// When to use colors: auto (terminal and no NO_COLOR), always, never
+    #[arg(long = "color", value_enum, default_value = "auto", value_name = "WHEN")]
+    pub color: ColorChoice,
 
     #[arg(long = "trace", default_value = "false")]
     pub trace: bool,
 }
 
 #[derive(ValueEnum, Clone, Debug, Copy)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color a stream. In auto mode only terminals are colored,
+    /// unless `NO_COLOR` is set to a non-empty value (https://no-color.org).
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                is_terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Report the entry and keep walking
+    Skip,
+    /// Stop the walk at the first error, still reporting the partial totals
+    Abort,
+    /// Stop the walk at the first error and fail without reporting anything
+    FailFast,
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Below the input path as it was given, like du
+    Given,
+    /// Relative to the input path the entry was found under
+    Relative,
+    /// Absolute, below the canonical input path
+    Absolute,
+    /// Only the name of the file or directory
+    Name,
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, Default, PartialEq, Eq)]
+pub enum TimeField {
+    /// Last modification of the content
+    #[default]
+    #[value(alias = "modification")]
+    Mtime,
+    /// Last access
+    #[value(alias = "access", alias = "use")]
+    Atime,
+    /// Last change of the content or the metadata
+    #[value(alias = "status")]
+    Ctime,
+}
+
+impl TimeField {
+    /// Name of the timestamp, as used for columns and keys
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mtime => "mtime",
+            Self::Atime => "atime",
+            Self::Ctime => "ctime",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum PercentOf {
+    Parent,
+    Total,
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
 pub enum SizeFormat {
+    /// Powers of 1000 with a unit (1.50 MB), the default. An alias of si
     Human,
+    /// Powers of 1000 with a unit (1.50 MB), like du --si
     Si,
+    /// Number of 512-byte blocks
     Blocks,
+    /// Number of bytes
     Bytes,
+    /// Powers of 1024 with a unit (1.43 MiB)
     Binary,
+    /// Number of bytes in hexadecimal
     Hex,
+    /// Number of KiB, rounded up
     Kilo,
+    /// Number of MiB, rounded up
     Mega,
+    /// Number of GiB, rounded up
     Giga,
 }
 
@@ -140,8 +526,22 @@ pub enum SortField {
 
 #[derive(ValueEnum, Clone, Debug, Copy)]
 pub enum OutputFormat {
+    /// Tab-separated size in bytes, file count and path, for scripts
     Raw,
+    /// Tab-separated size in bytes and path in path order, a format that
+    /// will not change across versions
+    Porcelain,
     Json,
+    /// One JSON object per line, written as entries are finalized
+    Ndjson,
+    /// ncdu JSON export, to browse with `ncdu -f`
+    Ncdu,
+    /// Nested `{ name, value, children }` JSON, for D3 treemaps and sunbursts
+    Treemap,
+    /// Prometheus text format, for node_exporter's textfile collector
+    Prometheus,
+    /// SQLite database, written to the file given with --db
+    Sqlite,
     // Csv,
     // Xml
 }
@@ -1,8 +1,15 @@
-use crate::cli::{Cli, SortField};
+use crate::cli::{Cli, ErrorPolicy, OutputFormat, PathStyle, PercentOf, SizeFormat, SortField, TimeField};
+use crate::core::aggregate::Entry;
+use crate::core::sample::Sample;
+use crate::error::WalkError;
 use crate::utils;
 use anyhow::{Context, Ok, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use num_format::{CustomFormat, SystemLocale};
 use regex::Regex;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -15,76 +22,426 @@ pub struct Config {
 
 impl Config {
     pub fn from_cli(cli: &Cli) -> Result<Self> {
-        let paths: Vec<PathBuf> = cli
-            .paths
-            .clone()
-            .into_iter()
-            .filter(|path| path.exists())
-            .collect();
-
-        if paths.is_empty() {
-            anyhow::bail!(
-                "Given paths do not exist: {}",
-                paths
-                    .into_iter()
-                    .map(|path| { path.display().to_string() })
-                    .collect::<String>()
-            )
+        let mut paths = cli.paths.clone();
+        if let Some(source) = &cli.files0_from {
+            paths.extend(read_files0_from(source)?);
+        } else if paths.is_empty() {
+            paths.push(PathBuf::from("."));
         }
 
+        let (paths, missing): (Vec<PathBuf>, Vec<PathBuf>) = paths.into_iter().partition(|path| path.exists());
+        let missing_list = || {
+            missing
+                .iter()
+                .map(|path| utils::escape_path(path))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        anyhow::ensure!(!paths.is_empty(), "Given paths do not exist: {}", missing_list());
+        anyhow::ensure!(!cli.strict || missing.is_empty(), "Given paths do not exist: {}", missing_list());
+        for path in &missing {
+            log::warn!("Skipping {}: does not exist", utils::escape_path(path));
+        }
+
+        let paths = dedupe_paths(paths);
+
+        // An ncdu export holds a single tree
+        if matches!(cli.output, Some(OutputFormat::Ncdu)) {
+            anyhow::ensure!(paths.len() == 1, "The ncdu export takes a single path");
+        }
+
+        let performance_config = PerformanceConfig::from_cli(cli, &paths)?;
         Ok(Config {
             paths,
             output_config: OutputConfig::from_cli(cli)?,
             filter_config: FilterConfig::from_cli(cli)?,
             traverse_config: TraverseConfig::from_cli(cli)?,
-            performance_config: PerformanceConfig::from_cli(cli)?,
+            performance_config,
         })
     }
 }
 
+/// Drop the paths that would be counted twice: duplicates, including
+/// symlinks to the same target, and paths inside another input path. The
+/// first of duplicate paths is kept, paths keep their order and spelling.
+fn dedupe_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let canonical: Vec<Option<PathBuf>> = paths.iter().map(|path| path.canonicalize().ok()).collect();
+    let covering = |i: usize| {
+        let own = canonical[i].as_ref()?;
+        canonical.iter().enumerate().position(|(j, other)| {
+            other.as_ref().is_some_and(|other| {
+                if other == own {
+                    j < i
+                } else {
+                    own.starts_with(other)
+                }
+            })
+        })
+    };
+
+    let mut kept = Vec::with_capacity(paths.len());
+    for (i, path) in paths.iter().enumerate() {
+        match covering(i) {
+            Some(j) => log::warn!(
+                "Skipping {}: already counted in {}",
+                utils::escape_path(path),
+                utils::escape_path(&paths[j])
+            ),
+            None => kept.push(path.clone()),
+        }
+    }
+    kept
+}
+
+/// Read NUL-separated paths from a file, or from stdin when the file is `-`
+fn read_files0_from(source: &Path) -> Result<Vec<PathBuf>> {
+    let mut buffer = Vec::new();
+    if source == Path::new("-") {
+        std::io::stdin()
+            .read_to_end(&mut buffer)
+            .context("Failed to read paths from stdin")?;
+    } else {
+        std::fs::File::open(source)
+            .and_then(|mut file| file.read_to_end(&mut buffer))
+            .with_context(|| format!("Failed to read paths from {}", source.display()))?;
+    }
+
+    // A trailing NUL does not start another path
+    let buffer = buffer.strip_suffix(b"\0").unwrap_or(&buffer);
+    if buffer.is_empty() {
+        return Ok(Vec::new());
+    }
+    buffer
+        .split(|&byte| byte == b'\0')
+        .map(|name| {
+            anyhow::ensure!(!name.is_empty(), "Invalid zero-length file name in {}", source.display());
+            Ok(utils::path_from_bytes(name))
+        })
+        .collect()
+}
+
+/// Digit grouping of the system locale. Locales that do not group digits,
+/// like the C locale, fall back to commas.
+fn thousands_format() -> CustomFormat {
+    SystemLocale::default()
+        .ok()
+        .filter(|locale| !locale.separator().is_empty())
+        .and_then(|locale| CustomFormat::builder().format(&locale).build().ok())
+        .unwrap_or_default()
+}
+
 #[derive(Debug, Clone)]
 pub struct OutputConfig {
     pub all: bool,
     pub dirs_only: bool,
     pub files_only: bool,
     pub apparent_size: bool,
+    /// Count inodes instead of bytes
+    pub inodes: bool,
     pub show_time: bool,
+    /// Timestamp displayed, sorted by and kept for each entry
+    pub time_field: TimeField,
+    /// strftime format of the displayed times
+    pub time_format: String,
     pub sort_field: Option<SortField>,
     pub reverse: bool,
+    pub top: Option<usize>,
+    pub histogram: bool,
+    pub by_extension: bool,
+    pub sparse: bool,
+    pub by_mount: bool,
+    pub empty: bool,
+    /// Smallest file compared for duplicate content, with --duplicates
+    pub duplicates: Option<u64>,
+    /// Bytes read from the start of a file to tell its type, with --by-type
+    pub by_type: Option<u64>,
+    /// Size a path must go over, with --exceeds
+    pub exceeds: Option<u64>,
     pub threshold: Option<u64>,
+    /// Fewest files a displayed directory holds in its subtree
+    pub min_files: Option<u64>,
+    pub size_format: SizeFormat,
+    /// What the --percent column is a share of
+    pub percent: Option<PercentOf>,
+    /// Whether the default output has a column with the files below each
+    /// entry
+    pub count: bool,
+    /// How entry paths are displayed
+    pub path_style: PathStyle,
+    pub block_size: Option<u64>,
+    /// Digit grouping for plain numbers, with --thousands
+    pub thousands: Option<CustomFormat>,
     pub total: bool,
+    /// Whether the default output ends with the total size line, only
+    /// the default output has one
+    pub total_line: bool,
+    /// Whether sizes in the default output are colored by size, only
+    /// when colors are enabled for where the results go
+    pub color_by_size: bool,
     pub summarize: bool,
+    /// Deepest level displayed, 0 for the input paths only. The walk
+    /// still goes to the bottom so totals stay complete.
+    pub max_depth: Option<usize>,
+    /// Only level displayed, with --depth-summary
+    pub depth_summary: Option<usize>,
+    pub output_format: Option<OutputFormat>,
+    /// File the results are written to instead of stdout
+    pub output_file: Option<PathBuf>,
+    /// File the skipped entries are written to as JSON Lines
+    pub error_json: Option<PathBuf>,
+    /// Database file written by the SQLite output
+    pub db: Option<PathBuf>,
+    /// Snapshot file the scan is saved to
+    pub save: Option<PathBuf>,
+    /// Snapshots to compare instead of scanning, old then new
+    pub diff: Option<(PathBuf, PathBuf)>,
+    /// Scan again on every change, with --watch
+    pub watch: bool,
+    pub null_terminated: bool,
+    pub stats: bool,
 }
 
 impl OutputConfig {
     fn from_cli(cli: &Cli) -> Result<Self> {
         // Parse threshold (human readable size) into number of bytes
+        let duplicates = match &cli.duplicates {
+            Some(size) => {
+                let size = utils::parse_size(size).context("Invalid duplicate size")?;
+                // Empty files all have the same content
+                anyhow::ensure!(size > 0, "Duplicate size must be at least 1 byte");
+                Some(size)
+            }
+            None => None,
+        };
+        let by_type = match &cli.by_type {
+            Some(size) => {
+                let size = utils::parse_size(size).context("Invalid --by-type size")?;
+                anyhow::ensure!(
+                    (1..=MAX_SNIFF_BYTES).contains(&size),
+                    "The --by-type size must be between 1 byte and 1M"
+                );
+                Some(size)
+            }
+            None => None,
+        };
+        let exceeds = match &cli.exceeds {
+            Some(size) => Some(utils::parse_size(size).context("Invalid --exceeds size")?),
+            None => None,
+        };
         let threshold = if let Some(t) = &cli.threshold {
             Some(utils::parse_size(t).context("Invalid threshold size")?)
         } else {
             None
         };
 
+        // Parse block size (human readable size) into number of bytes per block
+        let block_size = if let Some(b) = &cli.block_size {
+            Some(parse_block_size(b).context("Invalid block size")?)
+        } else {
+            None
+        };
+        let size_format = if cli.human_readable {
+            SizeFormat::Human
+        } else {
+            cli.format.unwrap_or(SizeFormat::Human)
+        };
+        // Any size option on the command line wins over the environment
+        let (size_format, block_size) = if block_size.is_none()
+            && !cli.human_readable
+            && cli.format.is_none()
+            && let Some(display) = env_size_display()?
+        {
+            display
+        } else {
+            (size_format, block_size)
+        };
+
+        if let Some(top) = cli.top {
+            anyhow::ensure!(top > 0, "Top count must be greater than 0");
+        }
+        if let Some(max_depth) = cli.max_depth {
+            anyhow::ensure!(max_depth <= 1000, "Max depth too large (maximum: 1000)");
+        }
+        if let Some(depth) = cli.depth_summary {
+            anyhow::ensure!(depth <= 1000, "Summary depth too large (maximum: 1000)");
+        }
+        anyhow::ensure!(
+            matches!(cli.output, Some(OutputFormat::Sqlite)) == cli.db.is_some(),
+            "The SQLite output and --db go together"
+        );
+        anyhow::ensure!(
+            cli.db.is_none() || cli.output_file.is_none(),
+            "The SQLite output is written to --db, not --output-file"
+        );
+        if matches!(cli.output, Some(OutputFormat::Ndjson)) {
+            anyhow::ensure!(
+                cli.sort.is_none()
+                    && cli.top.is_none()
+                    && !cli.histogram
+                    && !cli.by_extension
+                    && !cli.sparse
+                    && !cli.by_mount
+                    && !cli.empty
+                    && cli.duplicates.is_none()
+                    && cli.by_type.is_none()
+                    && cli.save.is_none(),
+                "The ndjson output streams entries and cannot be combined with \
+                 --sort, --top, --histogram, --by-extension, --sparse, --by-mount, --empty, --duplicates, \
+                 --by-type or --save"
+            );
+        }
+        if matches!(cli.output, Some(OutputFormat::Ncdu | OutputFormat::Treemap)) {
+            anyhow::ensure!(
+                cli.top.is_none()
+                    && !cli.histogram
+                    && !cli.by_extension
+                    && !cli.sparse
+                    && !cli.by_mount
+                    && !cli.empty
+                    && cli.duplicates.is_none()
+                    && cli.by_type.is_none(),
+                "Tree exports cannot be combined with \
+                 --top, --histogram, --by-extension, --sparse, --by-mount, --empty, --duplicates or --by-type"
+            );
+        }
+
         Ok(OutputConfig {
             all: cli.all,
             dirs_only: cli.dirs_only,
             files_only: cli.files_only,
             apparent_size: cli.apparent_size,
-            show_time: cli.show_time,
+            inodes: cli.inodes,
+            show_time: cli.time.is_some(),
+            time_field: cli.time.unwrap_or_default(),
+            time_format: parse_time_style(cli.time_style.as_deref().unwrap_or("long-iso"))
+                .context("Invalid time style")?,
             sort_field: cli.sort,
             reverse: cli.reverse,
+            top: cli.top,
+            histogram: cli.histogram,
+            by_extension: cli.by_extension,
+            sparse: cli.sparse,
+            by_mount: cli.by_mount,
+            empty: cli.empty,
+            duplicates,
+            by_type,
+            exceeds,
             threshold,
+            min_files: cli.min_files,
+            percent: cli.percent,
+            count: cli.count,
+            path_style: cli.path_style,
+            size_format,
+            block_size,
+            thousands: cli.thousands.then(thousands_format),
             total: cli.total,
+            total_line: !cli.no_total_line,
+            color_by_size: cli.color_by_size
+                && cli.color.enabled(cli.output_file.is_none() && std::io::stdout().is_terminal()),
             summarize: cli.summarize,
+            max_depth: cli.max_depth,
+            depth_summary: cli.depth_summary,
+            output_format: if cli.porcelain { Some(OutputFormat::Porcelain) } else { cli.output },
+            output_file: cli.output_file.clone(),
+            error_json: cli.error_json.clone(),
+            db: cli.db.clone(),
+            save: cli.save.clone(),
+            diff: match cli.diff.as_deref() {
+                Some([old, new]) => Some((old.clone(), new.clone())),
+                _ => None,
+            },
+            watch: cli.watch,
+            null_terminated: cli.null,
+            stats: cli.stats,
         })
     }
+
+    /// What an entry is displayed, sorted and filtered by: its number of
+    /// inodes with --inodes, otherwise its apparent size or disk usage
+    pub fn size_of(&self, entry: &Entry) -> u64 {
+        if self.inodes {
+            entry.inodes()
+        } else {
+            entry.size(self.apparent_size)
+        }
+    }
+
+    /// Whether file entries have to be retained for display
+    pub fn shows_files(&self) -> bool {
+        self.all || self.files_only || self.exports_tree()
+    }
+
+    /// Whether entries at `depth` are within --max-depth
+    pub fn within_depth(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max| depth <= max)
+    }
+
+    /// Whether entries are written as the walk produces them, instead of
+    /// after the scan
+    pub fn streams_entries(&self) -> bool {
+        matches!(self.output_format, Some(OutputFormat::Ndjson))
+    }
+
+    /// Whether the whole tree is written, unfiltered, as an ncdu or
+    /// treemap export
+    pub fn exports_tree(&self) -> bool {
+        matches!(self.output_format, Some(OutputFormat::Ncdu | OutputFormat::Treemap))
+    }
+}
+
+/// Compile glob patterns into a single set
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| WalkError::InvalidPattern(format!("{pattern}: {e}")))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Read exclude regexes from a file, one per line. Empty lines and lines
+/// starting with `#` are skipped.
+fn read_exclude_from(source: &Path) -> Result<Vec<Regex>> {
+    let contents = std::fs::read_to_string(source)
+        .with_context(|| format!("Failed to read exclude patterns from {}", source.display()))?;
+    let mut patterns = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let regex = Regex::new(line)
+            .map_err(|e| WalkError::InvalidPattern(format!("{line}: {e}")))
+            .with_context(|| format!("Invalid exclude pattern at {}:{}", source.display(), number + 1))?;
+        patterns.push(regex);
+    }
+    Ok(patterns)
 }
 
+/// Paths are matched in full, as they are displayed. An entry matching
+/// any exclude pattern, regex (from --exclude or --exclude-from) or glob,
+/// is skipped: a directory is not
+/// walked and a file not counted. When include patterns are given, a file
+/// is only counted if it matches one of them, regex or glob. Excludes take
+/// precedence over includes. Include patterns do not apply to directories,
+/// which are always walked.
 #[derive(Debug, Clone)]
 pub struct FilterConfig {
     pub exclude_patterns: Vec<Regex>,
     pub include_patterns: Vec<Regex>,
+    pub exclude_globs: GlobSet,
+    pub include_globs: GlobSet,
     pub exclude_caches: bool,
+    /// Entries ignored by `.gitignore` files are not walked nor counted
+    pub gitignore: bool,
+    /// Files outside this size range are not counted at all
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Files modified outside this window (seconds since epoch) are not counted
+    pub newer_than: Option<i64>,
+    pub older_than: Option<i64>,
+    /// Files not owned by this user and group are not counted
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
 }
 
 impl FilterConfig {
@@ -92,67 +449,316 @@ impl FilterConfig {
         let include_patterns = cli
             .include_patterns
             .iter()
-            .map(|p| Regex::new(p).with_context(|| format!("Invalid include pattern: {p}")))
-            .collect::<Result<Vec<_>>>()?;
+            .map(|p| Regex::new(p).map_err(|e| WalkError::InvalidPattern(format!("{p}: {e}"))))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid include pattern")?;
 
-        let exclude_patterns = cli
-            .exclude_patterns
-            .iter()
-            .map(|p| Regex::new(p).with_context(|| format!("Invalid exclude pattern: {p}")))
-            .collect::<Result<Vec<_>>>()?;
+        // Patterns read from files behave exactly like the ones given with --exclude
+        let mut exclude_patterns = Vec::new();
+        for source in &cli.exclude_from {
+            exclude_patterns.extend(read_exclude_from(source)?);
+        }
+        for p in &cli.exclude_patterns {
+            let regex = Regex::new(p)
+                .map_err(|e| WalkError::InvalidPattern(format!("{p}: {e}")))
+                .context("Invalid exclude pattern")?;
+            exclude_patterns.push(regex);
+        }
+
+        let include_globs = build_glob_set(&cli.include_globs).context("Invalid include glob")?;
+        let exclude_globs = build_glob_set(&cli.exclude_globs).context("Invalid exclude glob")?;
 
-        //TODO: load patterns from file
+        let min_size = match &cli.min_size {
+            Some(size) => Some(utils::parse_size(size).context("Invalid minimum size")?),
+            None => None,
+        };
+        let max_size = match &cli.max_size {
+            Some(size) => Some(utils::parse_size(size).context("Invalid maximum size")?),
+            None => None,
+        };
+        if let (Some(min), Some(max)) = (min_size, max_size) {
+            anyhow::ensure!(min <= max, "Minimum size must not be greater than maximum size");
+        }
+
+        // Ages are relative to the start of the scan
+        let now = jiff::Zoned::now();
+        let newer_than = match &cli.newer_than {
+            Some(time) => Some(utils::parse_time(time, &now).context("Invalid --newer-than")?),
+            None => None,
+        };
+        let older_than = match &cli.older_than {
+            Some(time) => Some(utils::parse_time(time, &now).context("Invalid --older-than")?),
+            None => None,
+        };
+
+        let uid = match &cli.user {
+            Some(user) => Some(utils::lookup_uid(user).context("Invalid user")?),
+            None => cli.uid,
+        };
+        let gid = match &cli.group {
+            Some(group) => Some(utils::lookup_gid(group).context("Invalid group")?),
+            None => cli.gid,
+        };
 
         Ok(FilterConfig {
             exclude_patterns,
             include_patterns,
+            exclude_globs,
+            include_globs,
             exclude_caches: cli.exclude_caches,
+            gitignore: cli.gitignore,
+            min_size,
+            max_size,
+            newer_than,
+            older_than,
+            uid,
+            gid,
         })
     }
+
+    /// Whether any include or exclude pattern is given
+    pub fn has_patterns(&self) -> bool {
+        !self.include_patterns.is_empty()
+            || !self.exclude_patterns.is_empty()
+            || !self.include_globs.is_empty()
+            || !self.exclude_globs.is_empty()
+    }
+
+    /// Whether an entry matches an exclude pattern
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.exclude_globs.is_match(path) {
+            return true;
+        }
+        if self.exclude_patterns.is_empty() {
+            return false;
+        }
+        let path = path.to_string_lossy();
+        self.exclude_patterns.iter().any(|re| re.is_match(&path))
+    }
+
+    /// Whether a file is counted under the include patterns, every file
+    /// is when none are given
+    pub fn is_included(&self, path: &Path) -> bool {
+        if self.include_patterns.is_empty() && self.include_globs.is_empty() {
+            return true;
+        }
+        if self.include_globs.is_match(path) {
+            return true;
+        }
+        let path = path.to_string_lossy();
+        self.include_patterns.iter().any(|re| re.is_match(&path))
+    }
+
+    /// Whether a file of this size is counted
+    pub fn size_in_range(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    /// Whether a file modified at `mtime` (seconds since epoch) is counted
+    pub fn mtime_in_range(&self, mtime: i64) -> bool {
+        self.newer_than.is_none_or(|cutoff| mtime > cutoff)
+            && self.older_than.is_none_or(|cutoff| mtime < cutoff)
+    }
+
+    /// Whether a file owned by `uid` and `gid` is counted
+    pub fn owner_matches(&self, uid: u32, gid: u32) -> bool {
+        self.uid.is_none_or(|wanted| uid == wanted) && self.gid.is_none_or(|wanted| gid == wanted)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TraverseConfig {
-    pub max_depth: Option<usize>,
+    /// Directories deeper than this are not walked, unlike --max-depth
+    /// this leaves their contents out of the totals
+    pub prune_depth: Option<usize>,
     pub min_depth: Option<usize>,
     pub follow_symlinks: bool,
     pub cross_filesystems: bool,
     pub count_hard_links: bool,
+    /// Whether devices, FIFOs and sockets are counted, they are skipped
+    /// by default
+    pub include_special: bool,
+    /// Whether only regular files are counted, directories then add
+    /// nothing of their own
+    pub regular_only: bool,
+    /// Whether the walk goes on past unreadable entries
+    pub on_error: ErrorPolicy,
+    /// Whether a scan that skipped entries still succeeds
+    pub ignore_errors: bool,
 }
 
 impl TraverseConfig {
     fn from_cli(cli: &Cli) -> Result<Self> {
-        if let Some(max_depth) = cli.max_depth {
-            anyhow::ensure!(max_depth > 0, "Max depth must be greater than 0");
-            anyhow::ensure!(max_depth <= 1000, "Max depth too large (maximum: 1000)");
-        };
+        if let Some(prune_depth) = cli.prune_depth {
+            anyhow::ensure!(prune_depth <= 1000, "Prune depth too large (maximum: 1000)");
+        }
         if let Some(min_depth) = cli.min_depth {
             anyhow::ensure!(min_depth <= 1000, "Min depth too large (maximum: 1000)");
         }
         Ok(TraverseConfig {
-            max_depth: cli.max_depth,
+            prune_depth: cli.prune_depth,
             min_depth: cli.min_depth,
             follow_symlinks: cli.dereference,
             cross_filesystems: !cli.one_file_system,
             count_hard_links: cli.count_links,
+            include_special: cli.include_special,
+            regular_only: cli.regular_only,
+            on_error: cli.on_error,
+            ignore_errors: cli.ignore_errors,
         })
     }
 }
 
+/// Parse a block size, which cannot be 0
+fn parse_block_size(s: &str) -> Result<u64> {
+    let block_size = utils::parse_size(s)?;
+    anyhow::ensure!(block_size > 0, "Block size must be greater than 0");
+    Ok(block_size)
+}
+
+/// Environment variables giving a default block size, in the order du
+/// reads them
+const BLOCK_SIZE_VARS: [&str; 3] = ["DU_BLOCK_SIZE", "BLOCK_SIZE", "BLOCKSIZE"];
+
+/// Block size used under `POSIXLY_CORRECT`, as POSIX requires of du
+const POSIX_BLOCK_SIZE: u64 = 512;
+
+/// Most bytes --by-type reads from the start of each file
+const MAX_SNIFF_BYTES: u64 = 1_000_000;
+
+/// Size display asked for by the environment, like du: the first of
+/// `BLOCK_SIZE_VARS` set, which may also be `human-readable` or `si`,
+/// then 512-byte blocks when `POSIXLY_CORRECT` is set. `None` leaves the
+/// default.
+fn env_size_display() -> Result<Option<(SizeFormat, Option<u64>)>> {
+    for var in BLOCK_SIZE_VARS {
+        // Set but empty counts as unset
+        let Some(value) = std::env::var_os(var).filter(|value| !value.is_empty()) else {
+            continue;
+        };
+        let value = value.to_string_lossy();
+        return Ok(Some(match value.trim() {
+            "human-readable" => (SizeFormat::Human, None),
+            "si" => (SizeFormat::Si, None),
+            size => (
+                SizeFormat::Human,
+                Some(parse_block_size(size).with_context(|| format!("Invalid block size in {var}"))?),
+            ),
+        }));
+    }
+    if std::env::var_os("POSIXLY_CORRECT").is_some() {
+        return Ok(Some((SizeFormat::Human, Some(POSIX_BLOCK_SIZE))));
+    }
+    Ok(None)
+}
+
+/// Parse a --max-time duration like `90s`, `30m` or `2h`
+fn parse_max_time(s: &str) -> Result<Duration> {
+    let duration = utils::parse_duration(s)?;
+    anyhow::ensure!(!duration.is_zero(), "Max time must be greater than 0");
+    Ok(duration)
+}
+
+/// Parse a --sample rate, a fraction like `0.1` or a percentage like `10%`
+fn parse_sample_rate(s: &str) -> Result<f64> {
+    let s = s.trim();
+    let rate = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>()? / 100.0,
+        None => s.parse::<f64>()?,
+    };
+    anyhow::ensure!(rate > 0.0 && rate <= 1.0, "Sample rate must be above 0 and at most 1 (100%)");
+    Ok(rate)
+}
+
+/// Parse a --time-style into a strftime format, the styles of `du
+/// --time-style` or a format of its own after a `+`
+fn parse_time_style(style: &str) -> Result<String> {
+    let format = match style {
+        "full-iso" => "%Y-%m-%d %H:%M:%S %z",
+        "long-iso" => "%Y-%m-%d %H:%M",
+        "iso" => "%Y-%m-%d",
+        _ => match style.strip_prefix('+') {
+            Some(format) => format,
+            None => anyhow::bail!("Expected full-iso, long-iso, iso or +FORMAT, got {style:?}"),
+        },
+    };
+    // Rejected now rather than on the first entry displayed
+    jiff::fmt::strtime::format(format, &jiff::Zoned::now())?;
+    Ok(format.to_string())
+}
+
+/// Threads used on spinning disks, where more parallel requests mostly add seeks
+const ROTATIONAL_THREADS: usize = 4;
+
+/// Pick a thread count from the kind of storage the paths are on: a few
+/// threads for spinning disks, more than the CPU count for solid state
+/// storage, which serves many requests in parallel
+fn auto_threads(paths: &[PathBuf]) -> usize {
+    let rotational: Vec<Option<bool>> = paths.iter().map(|path| utils::is_rotational(path)).collect();
+    let threads = if rotational.contains(&Some(true)) {
+        ROTATIONAL_THREADS
+    } else if !rotational.is_empty() && rotational.iter().all(|r| *r == Some(false)) {
+        num_cpus::get() * 2
+    } else {
+        num_cpus::get()
+    };
+    log::info!("Auto-selected {threads} threads (rotational storage: {rotational:?})");
+    threads
+}
+
 #[derive(Debug, Clone)]
 pub struct PerformanceConfig {
     pub threads: usize,
+    /// Threads of each device pool, replacing `threads` for the walk
+    pub threads_per_device: Option<usize>,
+    /// Maximum number of jobs a worker steals from the global queue at once
     pub batch_size: usize,
-    pub channel_buffer: usize,
+    /// Soft cap on the jobs a worker keeps in its local queue, the
+    /// excess goes to the global queue for the other workers
+    pub local_queue_cap: usize,
     pub cache_size_bytes: usize,
     pub use_cache: bool,
     pub buffer_errors: bool,
+    /// Wall-clock limit after which the walk is cancelled
+    pub max_time: Option<Duration>,
+    /// Entries after which the walk is cancelled
+    pub max_entries: Option<u64>,
+    /// Files counted when estimating with --sample
+    pub sample: Option<Sample>,
+    /// How workers wait for jobs while the others are busy
+    pub idle: IdleBackoff,
+}
+
+/// How an idle worker waits between two looks for a job: it spins for
+/// the first rounds, then yields its core, then parks until new jobs are
+/// queued. A job usually turns up within a few rounds while the walk is
+/// busy, near the end of a walk most workers find none for a long time
+/// and should not burn a core each looking for it.
+#[derive(Debug, Clone, Copy)]
+pub struct IdleBackoff {
+    /// Idle rounds spent spinning
+    pub spin_cycles: usize,
+    /// Idle rounds after which the worker parks instead of yielding
+    pub yield_cycles: usize,
+    /// Longest a parked worker waits before looking again, how late it
+    /// notices a cancelled walk
+    pub park_timeout: Duration,
+}
+
+impl Default for IdleBackoff {
+    fn default() -> Self {
+        Self {
+            spin_cycles: 4,
+            yield_cycles: 64,
+            park_timeout: Duration::from_millis(10),
+        }
+    }
 }
 
 impl PerformanceConfig {
-    fn from_cli(cli: &Cli) -> Result<Self> {
+    fn from_cli(cli: &Cli, paths: &[PathBuf]) -> Result<Self> {
         let threads = if cli.threads == 0 {
-            num_cpus::get()
+            auto_threads(paths)
         } else {
             anyhow::ensure!(
                 cli.threads <= 1000,
@@ -160,16 +766,44 @@ impl PerformanceConfig {
             );
             cli.threads
         };
+        if let Some(threads_per_device) = cli.threads_per_device {
+            anyhow::ensure!(
+                (1..=1000).contains(&threads_per_device),
+                "Threads per device must be between 1 and 1000"
+            );
+        }
+        anyhow::ensure!(cli.batch_size > 0, "Batch size must be greater than 0");
+        anyhow::ensure!(cli.local_queue_cap > 0, "Local queue cap must be greater than 0");
+        let max_time = match &cli.max_time {
+            Some(max_time) => Some(parse_max_time(max_time).context("Invalid max time")?),
+            None => None,
+        };
+        if let Some(max_entries) = cli.max_entries {
+            anyhow::ensure!(max_entries > 0, "Max entries must be greater than 0");
+        }
+        let sample = match &cli.sample {
+            Some(rate) => Some(Sample::new(parse_sample_rate(rate).context("Invalid sample rate")?)),
+            None => None,
+        };
         let cache_size_mb = cli.cache_size_mb.min(10_000); //cap at 10GB
         let cache_size_bytes = cache_size_mb.saturating_mul(1024 * 1024);
 
         Ok(PerformanceConfig {
             threads,
-            batch_size: 64,
-            channel_buffer: 1000,
+            threads_per_device: cli.threads_per_device,
+            batch_size: cli.batch_size,
+            local_queue_cap: cli.local_queue_cap,
             cache_size_bytes,
             use_cache: !cli.no_cache,
-            buffer_errors: cli.buffer_errors,
+            // The error report and the ncdu export need every error, not
+            // just a log line
+            buffer_errors: cli.buffer_errors
+                || cli.error_json.is_some()
+                || matches!(cli.output, Some(OutputFormat::Ncdu)),
+            max_time,
+            max_entries: cli.max_entries,
+            sample,
+            idle: IdleBackoff::default(),
         })
     }
 }
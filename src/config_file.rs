@@ -0,0 +1,195 @@
+use crate::cli::Cli;
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, Parser};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Options each section of the config file takes, by long name. The
+/// sections mirror the parts of `Config` the options end up in.
+const SECTIONS: [(&str, &[&str]); 4] = [
+    (
+        "output",
+        &[
+            "all",
+            "dirs-only",
+            "files-only",
+            "format",
+            "human-readable",
+            "percent",
+            "count",
+            "thousands",
+            "inodes",
+            "apparent-size",
+            "block-size",
+            "total",
+            "max-depth",
+            "depth-summary",
+            "summarize",
+            "sort",
+            "reverse",
+            "top",
+            "threshold",
+            "min-files",
+            "path-style",
+            "histogram",
+            "by-extension",
+            "sparse",
+            "by-mount",
+            "empty",
+            "duplicates",
+            "by-type",
+            "exceeds",
+            "output",
+            "porcelain",
+            "no-total-line",
+            "color-by-size",
+            "null",
+            "stats",
+            "time",
+            "time-style",
+        ],
+    ),
+    (
+        "filter",
+        &[
+            "include",
+            "exclude",
+            "exclude-from",
+            "iglob",
+            "eglob",
+            "gitignore",
+            "exclude-caches",
+            "min-size",
+            "max-size",
+            "newer-than",
+            "older-than",
+            "uid",
+            "user",
+            "gid",
+            "group",
+        ],
+    ),
+    (
+        "traverse",
+        &[
+            "count-links",
+            "include-special",
+            "regular-only",
+            "exclude-special",
+            "dereference",
+            "one-file-system",
+            "min-depth",
+            "prune-depth",
+            "on-error",
+            "ignore-errors",
+        ],
+    ),
+    (
+        "performance",
+        &["jobs", "threads-per-device", "max-time", "max-entries", "sample", "batch-size", "local-queue-cap", "cache-size", "no-cache", "buffer-errors"],
+    ),
+];
+
+/// The config file read when --config is not given:
+/// `$XDG_CONFIG_HOME/fdu/config.toml`, or `~/.config/fdu/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("fdu").join("config.toml"))
+}
+
+/// Parse the command line with the defaults of the config file merged
+/// under it.
+///
+/// An option given on the command line wins over the file, and so do the
+/// options it conflicts with. List options like `exclude` take the values
+/// of both, those of the file first. Exits on invalid arguments, like
+/// `Cli::parse`.
+pub fn parse_args(args: impl IntoIterator<Item = impl Into<OsString>>) -> Result<Cli> {
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let matches = Cli::command().get_matches_from(&args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if cli.no_config {
+        return Ok(cli);
+    }
+    let path = match &cli.config {
+        Some(path) => path.clone(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(cli),
+        },
+    };
+
+    let defaults = file_args(&path, &matches)?;
+    if defaults.is_empty() {
+        return Ok(cli);
+    }
+    let mut merged = Vec::with_capacity(args.len() + defaults.len());
+    merged.extend(args.first().cloned());
+    merged.extend(defaults);
+    merged.extend(args.into_iter().skip(1));
+    Cli::try_parse_from(merged).with_context(|| format!("Invalid option in {}", path.display()))
+}
+
+/// The options set in a config file, as command-line arguments, leaving
+/// out those the command line overrides
+fn file_args(path: &Path, matches: &ArgMatches) -> Result<Vec<OsString>> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let command = Cli::command();
+    let mut args = Vec::new();
+    for (section, options) in &table {
+        let Some((_, known)) = SECTIONS.iter().find(|(name, _)| name == section) else {
+            anyhow::bail!("Unknown section [{section}] in {}", path.display());
+        };
+        let toml::Value::Table(options) = options else {
+            anyhow::bail!("{section} in {} must be a [{section}] table", path.display());
+        };
+        for (name, value) in options {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(name.as_str()))
+                .filter(|_| known.contains(&name.as_str()))
+                .with_context(|| format!("Unknown option {name} in [{section}] of {}", path.display()))?;
+            if overridden(&command, arg, matches) {
+                continue;
+            }
+            let values = match value {
+                toml::Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                let value = match value {
+                    toml::Value::Boolean(true) => None,
+                    toml::Value::Boolean(false) => continue,
+                    toml::Value::String(value) => Some(value.clone()),
+                    toml::Value::Integer(value) => Some(value.to_string()),
+                    _ => anyhow::bail!("Unsupported value for {name} in {}", path.display()),
+                };
+                args.push(match value {
+                    Some(value) => format!("--{name}={value}").into(),
+                    None => format!("--{name}").into(),
+                });
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Whether the command line overrides an option: it gives the option, if
+/// it takes a single value, or one it conflicts with
+fn overridden(command: &Command, arg: &Arg, matches: &ArgMatches) -> bool {
+    let conflicts = command.get_arg_conflicts_with(arg);
+    command
+        .get_arguments()
+        .filter(|other| matches.value_source(other.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .any(|other| {
+            (other == arg && !matches!(arg.get_action(), ArgAction::Append))
+                || conflicts.contains(&other)
+                || command.get_arg_conflicts_with(other).contains(&arg)
+        })
+}
@@ -0,0 +1,214 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Size in bytes of the unit `st_blocks` is expressed in.
+///
+/// POSIX leaves the unit unspecified but Linux, macOS and the BSDs all
+/// report `st_blocks` in 512-byte units, independently of the filesystem
+/// block size (`st_blksize`), which is only the preferred I/O size.
+pub const BLOCK_UNIT: u64 = 512;
+
+/// Convert a number of `st_blocks` units to allocated bytes.
+/// Saturates instead of wrapping around on absurdly large totals.
+pub fn blocks_to_bytes(blocks: u64) -> u64 {
+    blocks.saturating_mul(BLOCK_UNIT)
+}
+
+/// Statistics collected by a worker for a single directory.
+/// Only covers the directory's direct children until rolled up by `aggregate`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirStats {
+    pub depth: usize,
+    /// Index of the input path this directory belongs to
+    pub root: usize,
+    pub blocks: u64,
+    /// Apparent size in bytes
+    pub apparent_size: u64,
+    pub files: u64,
+    /// Number of directories, the directory itself included
+    pub dirs: u64,
+    /// Most recent time selected with --time, modification time by
+    /// default (seconds since epoch)
+    pub time: i64,
+}
+
+impl DirStats {
+    pub fn new(depth: usize, root: usize) -> Self {
+        Self {
+            depth,
+            root,
+            dirs: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Fold another set of statistics into this one
+    pub fn merge(&mut self, other: &DirStats) {
+        self.blocks = self.blocks.saturating_add(other.blocks);
+        self.apparent_size = self.apparent_size.saturating_add(other.apparent_size);
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.time = self.time.max(other.time);
+    }
+}
+
+/// An aggregated entry with totals for its whole subtree
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub root: usize,
+    pub is_dir: bool,
+    pub blocks: u64,
+    pub apparent_size: u64,
+    pub files: u64,
+    /// Number of directories in the subtree, itself included, 0 for a file
+    pub dirs: u64,
+    /// Most recent time in the subtree selected with --time (seconds
+    /// since epoch)
+    pub time: i64,
+    /// Inode number of a file with more than one hard link, `None` for
+    /// directories and other files
+    pub ino: Option<u64>,
+}
+
+impl Entry {
+    /// Size in bytes: apparent size or disk usage
+    pub fn size(&self, apparent: bool) -> u64 {
+        if apparent {
+            self.apparent_size
+        } else {
+            blocks_to_bytes(self.blocks)
+        }
+    }
+
+    /// Number of inodes in the subtree: its files and directories
+    pub fn inodes(&self) -> u64 {
+        self.files + self.dirs
+    }
+
+    /// Bytes of the apparent size not backed by allocated blocks, which
+    /// is only the case for sparse files
+    pub fn unallocated(&self) -> u64 {
+        self.apparent_size.saturating_sub(blocks_to_bytes(self.blocks))
+    }
+}
+
+/// Statistics of the directories processed by a worker, bucketed by depth.
+///
+/// Every worker fills its own, so merging them at the end of the walk only
+/// appends buckets, and rolling them up deepest first needs no sort by
+/// depth of all the directories.
+#[derive(Default)]
+pub struct DirLevels(Vec<Vec<(PathBuf, DirStats)>>);
+
+impl DirLevels {
+    pub fn insert(&mut self, path: PathBuf, stats: DirStats) {
+        if self.0.len() <= stats.depth {
+            self.0.resize_with(stats.depth + 1, Vec::new);
+        }
+        self.0[stats.depth].push((path, stats));
+    }
+
+    /// Add the directories of another worker
+    pub fn merge(&mut self, other: DirLevels) {
+        if self.0.len() < other.0.len() {
+            self.0.resize_with(other.0.len(), Vec::new);
+        }
+        for (level, mut other) in self.0.iter_mut().zip(other.0) {
+            if level.len() < other.len() {
+                std::mem::swap(level, &mut other);
+            }
+            level.append(&mut other);
+        }
+    }
+}
+
+/// Minimum number of items sorted on several threads
+const PARALLEL_SORT_MIN_LEN: usize = 1 << 14;
+
+/// Sort with up to `threads` threads, no more than there are CPUs. Chunks
+/// are sorted in parallel, then the final stable sort finds them as sorted
+/// runs and only merges them.
+fn parallel_sort_by<T: Send>(items: &mut [T], threads: usize, compare: impl Fn(&T, &T) -> Ordering + Sync) {
+    let threads = threads.min(num_cpus::get());
+    if threads > 1 && items.len() >= PARALLEL_SORT_MIN_LEN {
+        let compare = &compare;
+        let sorted = crossbeam_utils::thread::scope(|scope| {
+            for chunk in items.chunks_mut(items.len().div_ceil(threads)) {
+                scope.spawn(move |_| chunk.sort_by(compare));
+            }
+        });
+        if let Err(panic) = sorted {
+            std::panic::resume_unwind(panic);
+        }
+    }
+    items.sort_by(compare);
+}
+
+/// Roll the per-directory statistics up into subtree totals.
+///
+/// Directories are processed deepest first so every child is complete
+/// before it gets folded into its parent. Within a depth, entries are
+/// grouped by root in input order, then ordered by path so the result
+/// does not depend on the order the walk visited them. The sorts run on
+/// up to `threads` threads, they dominate on trees with millions of
+/// directories.
+///
+/// File entries are already accounted for in their parent's statistics,
+/// they are only merged into the resulting list in the same order.
+pub fn aggregate(dirs: DirLevels, files: Vec<Entry>, threads: usize) -> Vec<Entry> {
+    let DirLevels(mut levels) = dirs;
+    for level in &mut levels {
+        parallel_sort_by(level, threads, |(a_path, a), (b_path, b)| (a.root, a_path).cmp(&(b.root, b_path)));
+    }
+    let (paths, mut stats): (Vec<PathBuf>, Vec<DirStats>) = levels.into_iter().rev().flatten().unzip();
+
+    let index: HashMap<&Path, usize> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| (path.as_path(), i))
+        .collect();
+
+    for (i, path) in paths.iter().enumerate() {
+        // Roots have no parent to fold into
+        if stats[i].depth == 0 {
+            continue;
+        }
+        if let Some(parent) = path.parent()
+            && let Some(&j) = index.get(parent)
+        {
+            let child = stats[i];
+            stats[j].merge(&child);
+        }
+    }
+    drop(index);
+
+    let mut entries: Vec<Entry> = paths
+        .into_iter()
+        .zip(stats)
+        .map(|(path, stats)| Entry {
+            path,
+            depth: stats.depth,
+            root: stats.root,
+            is_dir: true,
+            blocks: stats.blocks,
+            apparent_size: stats.apparent_size,
+            files: stats.files,
+            dirs: stats.dirs,
+            time: stats.time,
+            ino: None,
+        })
+        .collect();
+
+    if !files.is_empty() {
+        entries.extend(files);
+        parallel_sort_by(&mut entries, threads, |a, b| {
+            (std::cmp::Reverse(a.depth), a.root, &a.path).cmp(&(std::cmp::Reverse(b.depth), b.root, &b.path))
+        });
+    }
+    entries
+}
@@ -0,0 +1,87 @@
+use crate::core::worker::{Job, Parking};
+use crossbeam_deque::{Injector, Stealer, Worker};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicI64, Ordering},
+    },
+};
+
+/// The workers walking a single device, with --threads-per-device
+pub struct Pool {
+    pub dev: u64,
+    /// Jobs queued for the pool, by the workers of other devices or when
+    /// a local queue is full
+    pub injector: Arc<Injector<Job>>,
+    /// Stealers of the local queues of the pool's workers
+    pub stealers: Arc<Vec<Stealer<Job>>>,
+    /// Jobs of the pool queued or being processed, its workers stop at zero
+    pub job_counter: Arc<AtomicI64>,
+    /// Where the pool's workers wait for jobs
+    pub parking: Arc<Parking>,
+}
+
+/// The pools of a walk, one per device with jobs left.
+///
+/// A pool is created with the first job found on its device, and retired
+/// once its jobs run out. A later job on the same device creates a new
+/// pool, so a slow disk keeps its own workers busy without holding up the
+/// workers of the others.
+pub struct DevicePools {
+    threads_per_device: usize,
+    pools: Mutex<HashMap<u64, Arc<Pool>>>,
+}
+
+impl DevicePools {
+    pub fn new(threads_per_device: usize) -> Self {
+        Self {
+            threads_per_device,
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queue a job on the pool of `dev`. When the device has no pool, a new
+    /// one is returned with the local queues of its workers, for the caller
+    /// to spawn them.
+    pub fn push(&self, dev: u64, job: Job) -> Option<(Arc<Pool>, Vec<Worker<Job>>)> {
+        let mut pools = self.pools.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(pool) = pools.get(&dev) {
+            // Counted before it becomes visible, like the jobs a worker
+            // publishes to its own pool
+            pool.job_counter.fetch_add(1, Ordering::AcqRel);
+            pool.injector.push(job);
+            pool.parking.unpark_all();
+            return None;
+        }
+
+        let workers: Vec<Worker<Job>> = (0..self.threads_per_device).map(|_| Worker::new_fifo()).collect();
+        let pool = Arc::new(Pool {
+            dev,
+            injector: Arc::new(Injector::new()),
+            stealers: Arc::new(workers.iter().map(Worker::stealer).collect()),
+            job_counter: Arc::new(AtomicI64::new(1)),
+            parking: Arc::new(Parking::default()),
+        });
+        pool.injector.push(job);
+        pools.insert(dev, pool.clone());
+        log::debug!("Starting {} workers for device {dev}", self.threads_per_device);
+        Some((pool, workers))
+    }
+
+    /// Retire a pool whose jobs ran out. Returns false when a job was
+    /// queued on it in the meantime, its workers then carry on.
+    pub fn retire(&self, pool: &Pool) -> bool {
+        let mut pools = self.pools.lock().unwrap_or_else(PoisonError::into_inner);
+        // Jobs are only queued on a pool under the lock, or by its own
+        // workers while they hold a job that keeps the counter above zero
+        if pool.job_counter.load(Ordering::Acquire) != 0 {
+            return false;
+        }
+        if pools.get(&pool.dev).is_some_and(|current| std::ptr::eq(current.as_ref(), pool)) {
+            pools.remove(&pool.dev);
+            log::debug!("Workers for device {} done", pool.dev);
+        }
+        true
+    }
+}
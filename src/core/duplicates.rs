@@ -0,0 +1,169 @@
+use crate::utils::escape_path;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes read at each end of a file before hashing it whole. Files of the
+/// same size but different content mostly differ there already (headers,
+/// trailers, appended data).
+const ENDS_LEN: u64 = 4096;
+
+/// A regular file that may have a duplicate
+#[derive(Debug)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// Apparent size in bytes
+    pub len: u64,
+    pub dev: u64,
+    pub ino: u64,
+}
+
+/// Files with identical content
+#[derive(Debug)]
+pub struct DuplicateSet {
+    /// Size in bytes of each file
+    pub size: u64,
+    /// Paths of the files, in path order
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateSet {
+    /// Bytes freed by keeping a single copy
+    pub fn reclaimable(&self) -> u64 {
+        self.size.saturating_mul(self.paths.len() as u64 - 1)
+    }
+}
+
+/// Group files by identical content, the sets with the most reclaimable
+/// bytes first.
+///
+/// Files are compared by size first, then by a hash of their first and
+/// last `ENDS_LEN` bytes, and only files still alike are hashed whole,
+/// so most files are never read entirely. Hashing runs on up to `threads`
+/// threads. Hard links to the same inode are the same file, not
+/// duplicates, and a file that cannot be read is left out with a warning.
+pub fn find_duplicates(candidates: Vec<Candidate>, threads: usize) -> Vec<DuplicateSet> {
+    let mut inodes = HashSet::new();
+    // Inodes are unknown, 0, on Windows
+    let candidates = candidates
+        .into_iter()
+        .filter(|candidate| candidate.ino == 0 || inodes.insert((candidate.dev, candidate.ino)));
+    let by_size = regroup(vec![candidates.collect()], |candidate| candidate.len);
+
+    let by_ends = refine(by_size, threads, hash_ends);
+    // The ends of a small file are its whole content
+    let (small, large): (Vec<_>, Vec<_>) = by_ends
+        .into_iter()
+        .partition(|group| group[0].len <= 2 * ENDS_LEN);
+    let by_content = refine(large, threads, hash_whole);
+
+    let mut sets: Vec<DuplicateSet> = small
+        .into_iter()
+        .chain(by_content)
+        .map(|group| {
+            let size = group[0].len;
+            let mut paths: Vec<PathBuf> = group.into_iter().map(|candidate| candidate.path).collect();
+            paths.sort();
+            DuplicateSet { size, paths }
+        })
+        .collect();
+    sets.sort_by(|a, b| {
+        b.reclaimable()
+            .cmp(&a.reclaimable())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+    sets
+}
+
+/// Split groups by a key, keeping the resulting groups of two files or more
+fn regroup<T, K: Hash + Eq>(groups: Vec<Vec<T>>, key: impl Fn(&T) -> K) -> Vec<Vec<T>> {
+    let mut split = Vec::new();
+    for group in groups {
+        let mut by_key: HashMap<K, Vec<T>> = HashMap::new();
+        for candidate in group {
+            by_key.entry(key(&candidate)).or_default().push(candidate);
+        }
+        split.extend(by_key.into_values().filter(|group| group.len() > 1));
+    }
+    split
+}
+
+/// Split groups by a hash of their files computed on up to `threads`
+/// threads. Files that cannot be read are dropped.
+fn refine(
+    groups: Vec<Vec<Candidate>>,
+    threads: usize,
+    hash: impl Fn(&Candidate) -> io::Result<blake3::Hash> + Sync,
+) -> Vec<Vec<Candidate>> {
+    let files: Vec<&Candidate> = groups.iter().flatten().collect();
+    let next = AtomicUsize::new(0);
+    // Hashes in the order of `files`
+    let mut hashes = vec![None; files.len()];
+    let hashed = crossbeam_utils::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, files.len().max(1)))
+            .map(|_| {
+                scope.spawn(|_| {
+                    let mut hashed = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(file) = files.get(index) else {
+                            break;
+                        };
+                        match hash(file) {
+                            Ok(hash) => hashed.push((index, hash)),
+                            Err(err) => log::warn!("Skipping {}: {}", escape_path(&file.path), err),
+                        }
+                    }
+                    hashed
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect::<Vec<_>>()
+    })
+    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    for (index, hash) in hashed {
+        hashes[index] = Some(hash);
+    }
+
+    let mut hashes = hashes.into_iter();
+    let groups = groups
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .zip(&mut hashes)
+                .filter_map(|(file, hash)| Some((file, hash?)))
+                .collect()
+        })
+        .collect();
+    regroup(groups, |(_, hash)| *hash)
+        .into_iter()
+        .map(|group| group.into_iter().map(|(file, _)| file).collect())
+        .collect()
+}
+
+/// Hash the first and last `ENDS_LEN` bytes of a file, the whole file
+/// when it is smaller than both
+fn hash_ends(file: &Candidate) -> io::Result<blake3::Hash> {
+    let mut reader = File::open(&file.path)?;
+    let mut buffer = Vec::with_capacity(2 * ENDS_LEN as usize);
+    (&mut reader).take(ENDS_LEN).read_to_end(&mut buffer)?;
+    if file.len > 2 * ENDS_LEN {
+        reader.seek(SeekFrom::Start(file.len - ENDS_LEN))?;
+    }
+    reader.take(ENDS_LEN).read_to_end(&mut buffer)?;
+    Ok(blake3::hash(&buffer))
+}
+
+/// Hash the whole content of a file
+fn hash_whole(file: &Candidate) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(&file.path)?)?;
+    Ok(hasher.finalize())
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashMap, path::Path};
+
+/// Key used for files without an extension
+pub const NO_EXTENSION: &str = "<none>";
+
+/// Files counted for an extension
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtensionTotals {
+    pub files: u64,
+    pub blocks: u64,
+    /// Apparent size in bytes
+    pub apparent_size: u64,
+}
+
+impl ExtensionTotals {
+    /// Total size in bytes: apparent size or disk usage
+    pub fn size(&self, apparent: bool) -> u64 {
+        if apparent {
+            self.apparent_size
+        } else {
+            crate::core::aggregate::blocks_to_bytes(self.blocks)
+        }
+    }
+}
+
+/// Number of files and their total size per file extension
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionBreakdown {
+    /// Totals keyed by lowercased extension, or `NO_EXTENSION`
+    pub totals: HashMap<String, ExtensionTotals>,
+}
+
+impl ExtensionBreakdown {
+    /// Count a file under its extension
+    pub fn record(&mut self, path: &Path, apparent_size: u64, blocks: u64) {
+        let key = match path.extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => NO_EXTENSION.to_string(),
+        };
+        self.record_key(key, apparent_size, blocks);
+    }
+
+    /// Count a file under any key, like its content type with --by-type
+    pub fn record_key(&mut self, key: impl Into<String>, apparent_size: u64, blocks: u64) {
+        let totals = self.totals.entry(key.into()).or_default();
+        totals.files += 1;
+        totals.blocks = totals.blocks.saturating_add(blocks);
+        totals.apparent_size = totals.apparent_size.saturating_add(apparent_size);
+    }
+
+    /// Fold another breakdown into this one
+    pub fn merge(&mut self, other: ExtensionBreakdown) {
+        for (key, other) in other.totals {
+            let totals = self.totals.entry(key).or_default();
+            totals.files += other.files;
+            totals.blocks = totals.blocks.saturating_add(other.blocks);
+            totals.apparent_size = totals.apparent_size.saturating_add(other.apparent_size);
+        }
+    }
+
+    /// Extensions ordered by size, largest first, ties by extension
+    pub fn sorted(&self, apparent: bool) -> Vec<(&str, &ExtensionTotals)> {
+        let mut sorted: Vec<(&str, &ExtensionTotals)> = self
+            .totals
+            .iter()
+            .map(|(key, totals)| (key.as_str(), totals))
+            .collect();
+        sorted.sort_by(|(a_key, a), (b_key, b)| {
+            b.size(apparent)
+                .cmp(&a.size(apparent))
+                .then_with(|| a_key.cmp(b_key))
+        });
+        sorted
+    }
+}
@@ -0,0 +1,83 @@
+use infer::MatcherType;
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Type of files neither regular nor symlinks: devices, FIFOs and sockets
+pub const SPECIAL: &str = "special";
+/// Type of zero-byte files, which have no content to look at
+pub const EMPTY: &str = "empty";
+/// Type of files that could not be read
+pub const UNREADABLE: &str = "unreadable";
+
+/// Tells the content type of files from their first bytes, with --by-type.
+///
+/// Known formats are recognized by their magic bytes, anything else is
+/// text when it is valid UTF-8 without NUL bytes, binary otherwise. At most
+/// `limit` bytes of a file are read, into a buffer kept from one file to the
+/// next.
+pub struct Sniffer {
+    limit: u64,
+    buffer: Vec<u8>,
+}
+
+impl Sniffer {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Content type of a regular file of `len` bytes
+    pub fn classify(&mut self, path: &Path, len: u64) -> &'static str {
+        if len == 0 {
+            return EMPTY;
+        }
+        match self.read_start(path) {
+            Ok(()) => content_type(&self.buffer),
+            Err(err) => {
+                log::debug!("Cannot read {} to tell its type: {err}", path.display());
+                UNREADABLE
+            }
+        }
+    }
+
+    fn read_start(&mut self, path: &Path) -> io::Result<()> {
+        self.buffer.clear();
+        File::open(path)?.take(self.limit).read_to_end(&mut self.buffer)?;
+        Ok(())
+    }
+}
+
+/// Content type of the first bytes of a file
+fn content_type(start: &[u8]) -> &'static str {
+    if let Some(kind) = infer::get(start) {
+        return match kind.matcher_type() {
+            MatcherType::Image => "image",
+            MatcherType::Video => "video",
+            MatcherType::Audio => "audio",
+            MatcherType::Archive => "archive",
+            MatcherType::Doc | MatcherType::Book => "document",
+            MatcherType::Font => "font",
+            MatcherType::App => "executable",
+            MatcherType::Text => "text",
+            MatcherType::Custom => "other",
+        };
+    }
+    if is_text(start) { "text" } else { "binary" }
+}
+
+/// Whether bytes read from the start of a file look like text. A character
+/// cut off at the end of the bytes read does not count against it.
+fn is_text(start: &[u8]) -> bool {
+    if start.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(start) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    }
+}
@@ -0,0 +1,63 @@
+use crate::core::handle::DirHandle;
+use crate::utils::escape_path;
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{io, path::Path, sync::Arc};
+
+/// Name of the files holding the rules of a directory
+const GITIGNORE: &str = ".gitignore";
+
+/// The `.gitignore` rules in effect in a directory: its own, then those of
+/// its ancestors within the scanned tree. Deeper rules take precedence, so
+/// a nested `.gitignore` can re-include what a parent one ignores.
+pub struct IgnoreRules {
+    matcher: Gitignore,
+    parent: Option<Arc<IgnoreRules>>,
+}
+
+impl IgnoreRules {
+    /// The rules in effect in an open directory: those of its parent,
+    /// extended with its own `.gitignore` if it has one.
+    ///
+    /// An unreadable or invalid `.gitignore` is reported and skipped, the
+    /// directory is still walked with the rules that could be loaded.
+    pub fn load(dir: &DirHandle, path: &Path, parent: Option<Arc<IgnoreRules>>) -> Option<Arc<IgnoreRules>> {
+        let contents = match dir.read_file_at(GITIGNORE.as_ref()) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return parent,
+            Err(err) => {
+                log::warn!("Skipping {}: {}", escape_path(&path.join(GITIGNORE)), err);
+                return parent;
+            }
+        };
+
+        // Patterns are relative to the directory holding the file
+        let mut builder = GitignoreBuilder::new(path);
+        let from = path.join(GITIGNORE);
+        for line in String::from_utf8_lossy(&contents).lines() {
+            if let Err(err) = builder.add_line(Some(from.clone()), line) {
+                log::warn!("Skipping rule in {}: {}", escape_path(&from), err);
+            }
+        }
+        match builder.build() {
+            Ok(matcher) => Some(Arc::new(IgnoreRules { matcher, parent })),
+            Err(err) => {
+                log::warn!("Skipping {}: {}", escape_path(&from), err);
+                parent
+            }
+        }
+    }
+
+    /// Whether an entry is ignored, by the deepest rule that matches it
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut rules = Some(self);
+        while let Some(current) = rules {
+            match current.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => rules = current.parent.as_deref(),
+            }
+        }
+        false
+    }
+}
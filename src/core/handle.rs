@@ -0,0 +1,76 @@
+use crate::cli::TimeField;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+// The system calls behind the walk, one backend per platform. Each
+// provides the same `DirHandle`, `FileType`, `stat` and `lstat`.
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+#[cfg(unix)]
+pub use unix::{DirHandle, FileType, device_numbers, is_transient, lstat, make_device, stat};
+#[cfg(windows)]
+pub use windows::{DirHandle, FileType, lstat, stat};
+
+/// Number of times a system call failing with a transient error is made
+/// before the error is reported
+pub const TRANSIENT_ATTEMPTS: u32 = 4;
+
+/// Wait before the next attempt at a system call that failed with a
+/// transient error, a little longer after each attempt
+pub fn backoff(attempt: u32) {
+    std::thread::sleep(Duration::from_micros(50 << attempt));
+}
+
+/// Maximum number of directory handles kept open by queued jobs, when
+/// the file descriptor limit is unknown or unlimited.
+const DEFAULT_MAX_OPEN_HANDLES: usize = 512;
+
+/// Number of handles currently open
+static OPEN_HANDLES: AtomicUsize = AtomicUsize::new(0);
+
+impl DirHandle {
+    /// Whether another handle can be kept open for queued jobs
+    pub fn can_share() -> bool {
+        OPEN_HANDLES.load(Ordering::Relaxed) < DirHandle::max_open()
+    }
+}
+
+impl Drop for DirHandle {
+    fn drop(&mut self) {
+        OPEN_HANDLES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The fields of `struct stat` used by the walk
+#[derive(Debug, Clone, Copy)]
+pub struct FileStat {
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+    pub file_type: FileType,
+    pub uid: u32,
+    pub gid: u32,
+    /// Apparent size in bytes
+    pub len: u64,
+    /// Allocated size in `BLOCK_UNIT`s
+    pub blocks: u64,
+    /// Modification, access and change times (seconds since epoch)
+    pub mtime: i64,
+    pub atime: i64,
+    pub ctime: i64,
+}
+
+impl FileStat {
+    /// The timestamp selected with --time
+    pub fn time(&self, field: TimeField) -> i64 {
+        match field {
+            TimeField::Mtime => self.mtime,
+            TimeField::Atime => self.atime,
+            TimeField::Ctime => self.ctime,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::{DEFAULT_MAX_OPEN_HANDLES, FileStat, OPEN_HANDLES, TRANSIENT_ATTEMPTS, backoff};
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use rustix::fs::Dir;
+use rustix::fs::{AtFlags, CWD, Mode, OFlags, Stat};
+use rustix::io::Errno;
+use rustix::process::Resource;
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::Path,
+    sync::{OnceLock, atomic::Ordering},
+};
+
+pub use rustix::fs::FileType;
+
+/// Whether a system call failed because it was interrupted by a signal or
+/// the resource was briefly unavailable, and is worth making again
+pub fn is_transient(err: Errno) -> bool {
+    err == Errno::INTR || err == Errno::AGAIN
+}
+
+/// Make a system call, again up to `TRANSIENT_ATTEMPTS` times in total
+/// while it fails with a transient error. Other errors such as a denied
+/// permission or a missing entry are returned at once.
+fn retry<T>(mut call: impl FnMut() -> rustix::io::Result<T>) -> io::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match call() {
+            Err(err) if is_transient(err) && attempt < TRANSIENT_ATTEMPTS => {
+                backoff(attempt);
+                attempt += 1;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// An open directory. Entries are stat'ed and subdirectories opened
+/// relative to it, so their full paths never have to be resolved again.
+pub struct DirHandle {
+    fd: OwnedFd,
+}
+
+impl DirHandle {
+    fn new(fd: OwnedFd) -> Self {
+        OPEN_HANDLES.fetch_add(1, Ordering::Relaxed);
+        Self { fd }
+    }
+
+    /// Maximum number of directory handles kept open by queued jobs.
+    ///
+    /// A handle stays open as long as jobs for its subdirectories are
+    /// queued, so a wide tree could otherwise exhaust the file descriptor
+    /// limit. Half of the limit is used, past it subdirectories are opened
+    /// by path.
+    pub(super) fn max_open() -> usize {
+        static MAX: OnceLock<usize> = OnceLock::new();
+        *MAX.get_or_init(|| match rustix::process::getrlimit(Resource::Nofile).current {
+            Some(limit) => usize::try_from(limit / 2).unwrap_or(DEFAULT_MAX_OPEN_HANDLES),
+            None => DEFAULT_MAX_OPEN_HANDLES,
+        })
+    }
+
+    /// Open a directory by path, following a symlink like `read_dir` does
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let fd = retry(|| rustix::fs::open(path, OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC, Mode::empty()))?;
+        Ok(Self::new(fd))
+    }
+
+    /// Open a subdirectory of this directory
+    pub fn open_at(&self, name: &OsStr) -> io::Result<Self> {
+        let fd = retry(|| {
+            rustix::fs::openat(
+                &self.fd,
+                name,
+                OFlags::RDONLY | OFlags::DIRECTORY | OFlags::NOFOLLOW | OFlags::CLOEXEC,
+                Mode::empty(),
+            )
+        })?;
+        Ok(Self::new(fd))
+    }
+
+    /// Stat an entry of this directory, without following symlinks
+    pub fn stat_at(&self, name: &OsStr) -> io::Result<FileStat> {
+        Ok(retry(|| rustix::fs::statat(&self.fd, name, AtFlags::SYMLINK_NOFOLLOW))?.into())
+    }
+
+    /// Read the contents of a file in this directory
+    pub fn read_file_at(&self, name: &OsStr) -> io::Result<Vec<u8>> {
+        let fd = retry(|| rustix::fs::openat(&self.fd, name, OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty()))?;
+        let mut contents = Vec::new();
+        // Retries reads interrupted by a signal
+        File::from(fd).read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// The underlying file descriptor
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+
+    /// Iterate over the entries of this directory
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn entries(&self) -> io::Result<Dir> {
+        retry(|| Dir::read_from(&self.fd))
+    }
+}
+
+/// Major and minor numbers of a device number as kept in `FileStat::dev`
+// `dev_t` is 64 bits on Linux and the BSDs but 32 on macOS
+#[allow(clippy::unnecessary_cast)]
+pub fn device_numbers(dev: u64) -> (u32, u32) {
+    let dev = dev as rustix::fs::Dev;
+    (rustix::fs::major(dev), rustix::fs::minor(dev))
+}
+
+/// The device number kept in `FileStat::dev` for major and minor numbers
+#[allow(clippy::unnecessary_cast)]
+pub fn make_device(major: u32, minor: u32) -> u64 {
+    rustix::fs::makedev(major, minor) as u64
+}
+
+/// Stat a path, following a final symlink like `metadata`
+pub fn stat(path: &Path) -> io::Result<FileStat> {
+    Ok(retry(|| rustix::fs::statat(CWD, path, AtFlags::empty()))?.into())
+}
+
+/// Stat a path without following a final symlink, like `symlink_metadata`
+pub fn lstat(path: &Path) -> io::Result<FileStat> {
+    Ok(retry(|| rustix::fs::statat(CWD, path, AtFlags::SYMLINK_NOFOLLOW))?.into())
+}
+
+// The width of the `struct stat` fields varies across platforms
+#[allow(clippy::unnecessary_cast)]
+impl From<Stat> for FileStat {
+    fn from(stat: Stat) -> Self {
+        Self {
+            dev: stat.st_dev as u64,
+            ino: stat.st_ino as u64,
+            nlink: stat.st_nlink as u64,
+            file_type: FileType::from_raw_mode(stat.st_mode as _),
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            len: stat.st_size as u64,
+            blocks: stat.st_blocks as u64,
+            mtime: stat.st_mtime as i64,
+            atime: stat.st_atime as i64,
+            ctime: stat.st_ctime as i64,
+        }
+    }
+}
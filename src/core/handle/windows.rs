@@ -0,0 +1,133 @@
+use super::{DEFAULT_MAX_OPEN_HANDLES, FileStat, OPEN_HANDLES};
+use crate::core::aggregate::BLOCK_UNIT;
+use std::{
+    ffi::OsStr,
+    fs::{Metadata, ReadDir},
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Type of a directory entry. The same variants as on Unix, Windows only
+/// ever reports the first three.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    RegularFile,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+    CharacterDevice,
+    BlockDevice,
+    Unknown,
+}
+
+impl From<std::fs::FileType> for FileType {
+    fn from(file_type: std::fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            FileType::Symlink
+        } else if file_type.is_dir() {
+            FileType::Directory
+        } else if file_type.is_file() {
+            FileType::RegularFile
+        } else {
+            FileType::Unknown
+        }
+    }
+}
+
+/// A directory, by path: Windows has no equivalent of `openat`, entries
+/// are stat'ed and subdirectories opened through their full path.
+pub struct DirHandle {
+    path: PathBuf,
+}
+
+impl DirHandle {
+    fn new(path: PathBuf) -> Self {
+        OPEN_HANDLES.fetch_add(1, Ordering::Relaxed);
+        Self { path }
+    }
+
+    /// Maximum number of directory handles kept by queued jobs. A handle
+    /// holds no system resource here, the limit only bounds memory.
+    pub(super) fn max_open() -> usize {
+        DEFAULT_MAX_OPEN_HANDLES
+    }
+
+    /// Open a directory by path, following a symlink like `read_dir` does
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if !std::fs::metadata(path)?.is_dir() {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        Ok(Self::new(path.to_path_buf()))
+    }
+
+    /// Open a subdirectory of this directory, not through a symlink or a
+    /// junction
+    pub fn open_at(&self, name: &OsStr) -> io::Result<Self> {
+        let path = self.path.join(name);
+        if !std::fs::symlink_metadata(&path)?.is_dir() {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        Ok(Self::new(path))
+    }
+
+    /// Stat an entry of this directory, without following symlinks
+    pub fn stat_at(&self, name: &OsStr) -> io::Result<FileStat> {
+        lstat(&self.path.join(name))
+    }
+
+    /// Read the contents of a file in this directory
+    pub fn read_file_at(&self, name: &OsStr) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path.join(name))
+    }
+
+    /// Iterate over the entries of this directory
+    pub fn entries(&self) -> io::Result<ReadDir> {
+        std::fs::read_dir(&self.path)
+    }
+}
+
+/// Stat a path, following a final symlink like `metadata`
+pub fn stat(path: &Path) -> io::Result<FileStat> {
+    Ok(std::fs::metadata(path)?.into())
+}
+
+/// Stat a path without following a final symlink, like `symlink_metadata`
+pub fn lstat(path: &Path) -> io::Result<FileStat> {
+    Ok(std::fs::symlink_metadata(path)?.into())
+}
+
+/// Seconds since the epoch, 0 when the filesystem does not keep the time
+fn epoch_seconds(time: io::Result<SystemTime>) -> i64 {
+    match time.map(|time| time.duration_since(UNIX_EPOCH)) {
+        Ok(Ok(since)) => i64::try_from(since.as_secs()).unwrap_or(i64::MAX),
+        Ok(Err(before)) => i64::try_from(before.duration().as_secs()).map_or(i64::MIN, |secs| -secs),
+        Err(_) => 0,
+    }
+}
+
+/// Windows has no device and inode numbers, link counts, owners or
+/// allocated blocks in its standard metadata. Files are never seen as
+/// hard links, and their disk usage is their apparent size rounded up to
+/// whole blocks. There is no change time either, the modification time
+/// stands in for it.
+impl From<Metadata> for FileStat {
+    fn from(metadata: Metadata) -> Self {
+        let mtime = epoch_seconds(metadata.modified());
+        Self {
+            dev: 0,
+            ino: 0,
+            nlink: 1,
+            file_type: metadata.file_type().into(),
+            uid: 0,
+            gid: 0,
+            len: metadata.len(),
+            blocks: metadata.len().div_ceil(BLOCK_UNIT),
+            mtime,
+            atime: epoch_seconds(metadata.accessed()),
+            ctime: mtime,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Set of (device, inode) pairs already counted, shared by all workers
+/// of a walk so a file with several hard links is only counted once,
+/// even when the links live under different input paths.
+pub struct HardLinkCache {
+    seen: Mutex<HashSet<(u64, u64)>>,
+    /// Maximum number of inodes remembered
+    capacity: usize,
+    full_warned: AtomicBool,
+}
+
+impl HardLinkCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            seen: Mutex::new(HashSet::new()),
+            capacity: capacity_bytes / size_of::<(u64, u64)>(),
+            full_warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns true the first time an inode is seen.
+    /// Once the cache is full every unseen inode is reported as new.
+    pub fn first_visit(&self, dev: u64, ino: u64) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        if seen.contains(&(dev, ino)) {
+            return false;
+        }
+        if seen.len() >= self.capacity {
+            if !self.full_warned.swap(true, Ordering::Relaxed) {
+                log::warn!("Hard link cache is full, further hard links may be counted twice");
+            }
+            return true;
+        }
+        seen.insert((dev, ino));
+        true
+    }
+}
@@ -0,0 +1,72 @@
+/// Upper bounds (exclusive) of the file size buckets in bytes. Each bucket
+/// is 16 times wider than the previous one, the last bucket is unbounded.
+pub const BUCKET_BOUNDS: [u64; 7] = [
+    4 << 10,
+    64 << 10,
+    1 << 20,
+    16 << 20,
+    256 << 20,
+    4 << 30,
+    64 << 30,
+];
+
+/// Files counted in a size bucket
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bucket {
+    /// Smallest file size in the bucket
+    pub min: u64,
+    /// Size the bucket stops at (exclusive), `None` for the last bucket
+    pub max: Option<u64>,
+    pub files: u64,
+    pub blocks: u64,
+    /// Apparent size in bytes
+    pub apparent_size: u64,
+}
+
+impl Bucket {
+    /// Total size of the bucket in bytes: apparent size or disk usage
+    pub fn size(&self, apparent: bool) -> u64 {
+        if apparent {
+            self.apparent_size
+        } else {
+            crate::core::aggregate::blocks_to_bytes(self.blocks)
+        }
+    }
+}
+
+/// Number of files and their total size per file size range
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub buckets: [Bucket; BUCKET_BOUNDS.len() + 1],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        let mut buckets = [Bucket::default(); BUCKET_BOUNDS.len() + 1];
+        for (i, bucket) in buckets.iter_mut().enumerate() {
+            bucket.min = if i == 0 { 0 } else { BUCKET_BOUNDS[i - 1] };
+            bucket.max = BUCKET_BOUNDS.get(i).copied();
+        }
+        Self { buckets }
+    }
+}
+
+impl Histogram {
+    /// Count a file, bucketed by its apparent size
+    pub fn record(&mut self, apparent_size: u64, blocks: u64) {
+        let index = BUCKET_BOUNDS.partition_point(|&bound| bound <= apparent_size);
+        let bucket = &mut self.buckets[index];
+        bucket.files += 1;
+        bucket.blocks = bucket.blocks.saturating_add(blocks);
+        bucket.apparent_size = bucket.apparent_size.saturating_add(apparent_size);
+    }
+
+    /// Fold another histogram into this one
+    pub fn merge(&mut self, other: &Histogram) {
+        for (bucket, other) in self.buckets.iter_mut().zip(&other.buckets) {
+            bucket.files += other.files;
+            bucket.blocks = bucket.blocks.saturating_add(other.blocks);
+            bucket.apparent_size = bucket.apparent_size.saturating_add(other.apparent_size);
+        }
+    }
+}
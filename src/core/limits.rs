@@ -0,0 +1,115 @@
+use crate::config::Config;
+use std::{
+    path::PathBuf,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// An input path found to take more than the --exceeds limit
+#[derive(Debug, Clone)]
+pub struct Exceeded {
+    /// Index of the input path
+    pub root: usize,
+    /// The entry whose size took the total past the limit
+    pub tripped_by: PathBuf,
+}
+
+/// Running total of each input path, shared by all workers of a walk,
+/// with --exceeds.
+///
+/// The totals only grow while the walk goes on, so the first time one
+/// passes the limit the answer is known and the rest of the walk can be
+/// skipped.
+pub struct Quota {
+    /// Size in bytes a total must go over
+    limit: u64,
+    /// Bytes counted so far, per input path
+    totals: Vec<AtomicU64>,
+    exceeded: OnceLock<Exceeded>,
+}
+
+impl Quota {
+    pub fn new(limit: u64, roots: usize) -> Self {
+        Self {
+            limit,
+            totals: (0..roots).map(|_| AtomicU64::new(0)).collect(),
+            exceeded: OnceLock::new(),
+        }
+    }
+
+    /// Add `size` bytes to the total of `root`. Returns true for the
+    /// entry that takes the first total past the limit, `path` being only
+    /// built then.
+    pub fn add(&self, root: usize, size: u64, path: impl FnOnce() -> PathBuf) -> bool {
+        let before = self.totals[root].fetch_add(size, Ordering::Relaxed);
+        if before > self.limit || before.saturating_add(size) <= self.limit {
+            return false;
+        }
+        self.exceeded
+            .set(Exceeded {
+                root,
+                tripped_by: path(),
+            })
+            .is_ok()
+    }
+
+    /// The input path that went over the limit first, if any did
+    pub fn into_exceeded(self) -> Option<Exceeded> {
+        self.exceeded.into_inner()
+    }
+}
+
+/// Number of entries processed by all workers of a walk, with
+/// --max-entries, a guard against directories gone out of hand
+pub struct EntryLimit {
+    /// Entries the walk may process
+    limit: u64,
+    count: AtomicU64,
+    /// The directory whose entries took the count over the limit
+    exceeded: OnceLock<PathBuf>,
+}
+
+impl EntryLimit {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            count: AtomicU64::new(0),
+            exceeded: OnceLock::new(),
+        }
+    }
+
+    /// Count the entries of a job. Returns true for the job that takes the
+    /// count past the limit, `path` being only built then.
+    pub fn add(&self, entries: u64, path: impl FnOnce() -> PathBuf) -> bool {
+        let before = self.count.fetch_add(entries, Ordering::Relaxed);
+        if before > self.limit || before.saturating_add(entries) <= self.limit {
+            return false;
+        }
+        self.exceeded.set(path()).is_ok()
+    }
+
+    /// The directory that took the count over the limit, if any did
+    pub fn into_exceeded(self) -> Option<PathBuf> {
+        self.exceeded.into_inner()
+    }
+}
+
+/// The limits of a walk, shared by all its workers. Reaching one cancels
+/// the walk.
+pub struct Limits {
+    /// Running totals of the input paths, only with --exceeds
+    pub quota: Option<Quota>,
+    /// Entries processed, only with --max-entries
+    pub entries: Option<EntryLimit>,
+}
+
+impl Limits {
+    pub fn new(config: &Config, roots: usize) -> Self {
+        Self {
+            quota: config.output_config.exceeds.map(|limit| Quota::new(limit, roots)),
+            entries: config.performance_config.max_entries.map(EntryLimit::new),
+        }
+    }
+}
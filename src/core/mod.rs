@@ -1,2 +1,16 @@
+pub mod aggregate;
+pub mod devices;
+pub mod duplicates;
+pub mod extensions;
+pub mod filetypes;
+pub mod gitignore;
+pub mod handle;
+pub mod hardlinks;
+pub mod histogram;
+pub mod mounts;
+pub mod processor;
+pub mod limits;
+pub mod retention;
+pub mod sample;
 pub mod walker;
 pub mod worker;
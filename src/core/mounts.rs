@@ -0,0 +1,155 @@
+use crate::core::aggregate::blocks_to_bytes;
+#[cfg(unix)]
+use crate::core::handle;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Files and directories counted on a filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MountTotals {
+    pub files: u64,
+    pub blocks: u64,
+    /// Apparent size in bytes
+    pub apparent_size: u64,
+}
+
+impl MountTotals {
+    /// Total size in bytes: apparent size or disk usage
+    pub fn size(&self, apparent: bool) -> u64 {
+        if apparent {
+            self.apparent_size
+        } else {
+            blocks_to_bytes(self.blocks)
+        }
+    }
+
+    fn add(&mut self, other: &MountTotals) {
+        self.files += other.files;
+        self.blocks = self.blocks.saturating_add(other.blocks);
+        self.apparent_size = self.apparent_size.saturating_add(other.apparent_size);
+    }
+}
+
+/// Number of files and their total size per filesystem, keyed by device
+#[derive(Debug, Clone, Default)]
+pub struct MountBreakdown {
+    pub totals: HashMap<u64, MountTotals>,
+}
+
+impl MountBreakdown {
+    /// Count a file on its device
+    pub fn record_file(&mut self, dev: u64, apparent_size: u64, blocks: u64) {
+        self.totals.entry(dev).or_default().add(&MountTotals {
+            files: 1,
+            blocks,
+            apparent_size,
+        });
+    }
+
+    /// Count the blocks of a directory itself on its device
+    pub fn record_dir(&mut self, dev: u64, apparent_size: u64, blocks: u64) {
+        self.totals.entry(dev).or_default().add(&MountTotals {
+            files: 0,
+            blocks,
+            apparent_size,
+        });
+    }
+
+    /// Fold another breakdown into this one
+    pub fn merge(&mut self, other: MountBreakdown) {
+        for (dev, other) in other.totals {
+            self.totals.entry(dev).or_default().add(&other);
+        }
+    }
+
+    /// Totals labeled with their mount point, largest first. Devices
+    /// without a known mount point are labeled `device <major>:<minor>`.
+    pub fn sorted(&self, apparent: bool) -> Vec<(String, &MountTotals)> {
+        let mount_points = mount_points();
+        let mut sorted: Vec<(String, &MountTotals)> = self
+            .totals
+            .iter()
+            .map(|(dev, totals)| (device_label(*dev, &mount_points), totals))
+            .collect();
+        sorted.sort_by(|(a_label, a), (b_label, b)| {
+            b.size(apparent)
+                .cmp(&a.size(apparent))
+                .then_with(|| a_label.cmp(b_label))
+        });
+        sorted
+    }
+}
+
+/// Mount point of a device, or `device <major>:<minor>` when unknown
+pub fn device_label(dev: u64, mount_points: &HashMap<u64, PathBuf>) -> String {
+    match mount_points.get(&dev) {
+        Some(mount_point) => mount_point.to_string_lossy().into_owned(),
+        #[cfg(unix)]
+        None => {
+            let (major, minor) = handle::device_numbers(dev);
+            format!("device {major}:{minor}")
+        }
+        #[cfg(windows)]
+        None => format!("device {dev}"),
+    }
+}
+
+/// Mount point of each device, from `/proc/self/mountinfo`. A device
+/// mounted several times (bind mounts) keeps its first mount point.
+/// Empty outside Linux.
+#[cfg(unix)]
+pub fn mount_points() -> HashMap<u64, PathBuf> {
+    let mut mount_points = HashMap::new();
+    let Ok(mountinfo) = std::fs::read("/proc/self/mountinfo") else {
+        return mount_points;
+    };
+    // Fields: mount id, parent id, major:minor, root, mount point, ...
+    for line in mountinfo.split(|&byte| byte == b'\n') {
+        let mut fields = line.split(|&byte| byte == b' ').skip(2);
+        let (Some(device), Some(mount_point)) = (fields.next(), fields.nth(1)) else {
+            continue;
+        };
+        let Some((major, minor)) = std::str::from_utf8(device).ok().and_then(|device| device.split_once(':'))
+        else {
+            continue;
+        };
+        let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) else {
+            continue;
+        };
+        mount_points
+            .entry(handle::make_device(major, minor))
+            .or_insert_with(|| unescape_mount_point(mount_point));
+    }
+    mount_points
+}
+
+/// Mount point of each device, unknown on Windows
+#[cfg(windows)]
+pub fn mount_points() -> HashMap<u64, PathBuf> {
+    HashMap::new()
+}
+
+/// Undo the octal escapes (`\040` for a space) of mountinfo paths
+#[cfg(unix)]
+fn unescape_mount_point(escaped: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        let octal = escaped
+            .get(i + 1..i + 4)
+            .filter(|_| escaped[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 4;
+            }
+            None => {
+                bytes.push(escaped[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
@@ -0,0 +1,719 @@
+use crate::cli::ErrorPolicy;
+use crate::config::Config;
+use crate::core::aggregate::{DirLevels, DirStats, Entry, blocks_to_bytes};
+use crate::core::duplicates::Candidate;
+use crate::core::extensions::ExtensionBreakdown;
+use crate::core::filetypes::{self, Sniffer};
+use crate::core::gitignore::IgnoreRules;
+use crate::core::hardlinks::HardLinkCache;
+use crate::core::histogram::Histogram;
+use crate::core::mounts::MountBreakdown;
+use crate::core::limits::Limits;
+use crate::core::retention::RetainedFiles;
+#[cfg(windows)]
+use crate::core::sample;
+use crate::core::sample::Sample;
+use crate::core::walker::Visitor;
+use crate::core::worker::{Job, WorkerResult};
+use crate::error::{ErrorCounts, WalkError};
+use crate::utils::escape_path;
+use crate::core::handle::{self, DirHandle, FileStat, FileType};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use rustix::fs::RawDir;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::{
+    any::Any,
+    ffi::OsStr,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Size of the buffer directory entries are read into. Large directories
+/// are read in batches of this many bytes of entries per system call.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const DIRENT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Processes jobs for a walker: reads directories, stats files and
+/// accumulates the statistics. It knows nothing about scheduling, the
+/// directory jobs it produces are left in `new_jobs` for the walker.
+pub struct JobProcessor<'a> {
+    /// Id of the owning worker, for logging
+    id: usize,
+
+    /// Configuration
+    config: &'a Config,
+
+    /// Cancellation flag of the walk, set on the first error unless
+    /// errors are skipped
+    cancelled: &'a AtomicBool,
+
+    /// Shared set of hard-linked inodes already counted, if deduplicating
+    hard_links: Option<&'a HardLinkCache>,
+
+    /// Limits shared with the other workers, which cancel the walk
+    limits: &'a Limits,
+
+    /// Directory jobs produced by the last processed job
+    pub new_jobs: Vec<Job>,
+
+    /// Statistics
+    pub dirs_processed: usize,
+    pub files_processed: usize,
+    pub error_counts: ErrorCounts,
+    errors: Vec<WalkError>,
+
+    /// Data that can be calculated walking
+    total_blocks: u64,
+    total_apparent_size: u64,
+
+    /// Statistics of the directories processed
+    dirs: DirLevels,
+
+    /// File entries, only retained when they can be displayed
+    files: RetainedFiles,
+
+    /// Callback receiving the file entries instead of retaining them
+    visit: Option<&'a Visitor<'a>>,
+
+    /// `.gitignore` rules in effect in the directory being read
+    ignore: Option<Arc<IgnoreRules>>,
+
+    /// Device of the directory being read, given to its subdirectory jobs
+    dev: Option<u64>,
+
+    /// Buffer directory entries are read into, reused for every directory
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    dirent_buffer: Vec<u8>,
+
+    /// File size histogram, only with --histogram
+    histogram: Option<Histogram>,
+
+    /// Totals per file extension, only with --by-extension
+    extensions: Option<ExtensionBreakdown>,
+
+    /// Totals per content type, only with --by-type
+    types: Option<ExtensionBreakdown>,
+    /// Reads the start of files for their content type, with --by-type
+    sniffer: Option<Sniffer>,
+
+    /// Files with less allocated than apparent size, only with --sparse
+    sparse_files: Option<Vec<Entry>>,
+
+    /// Totals per filesystem, only with --by-mount
+    mounts: Option<MountBreakdown>,
+
+    /// Directories without entries and zero-byte files, only with --empty
+    empty: Option<Vec<PathBuf>>,
+
+    /// Files that may have duplicate content, only with --duplicates
+    duplicates: Option<Vec<Candidate>>,
+
+    /// Whether devices, FIFOs and sockets are counted or skipped
+    count_special: bool,
+    /// Number of devices, FIFOs and sockets seen, counted or not
+    special_files: usize,
+    /// Entries that are not regular files, skipped with --regular-only
+    non_regular: u64,
+
+    /// Jobs cut short by a panic
+    panics: usize,
+
+    /// Files counted, only with --sample
+    sample: Option<Sample>,
+    /// Variance of the estimated total of the files counted, with --sample
+    sample_variance: f64,
+}
+
+impl<'a> JobProcessor<'a> {
+    pub fn new(
+        id: usize,
+        config: &'a Config,
+        hard_links: Option<&'a HardLinkCache>,
+        limits: &'a Limits,
+        visit: Option<&'a Visitor<'a>>,
+        cancelled: &'a AtomicBool,
+    ) -> Self {
+        Self {
+            id,
+            config,
+            cancelled,
+            hard_links,
+            limits,
+            new_jobs: Vec::new(),
+            dirs_processed: 0,
+            files_processed: 0,
+            error_counts: ErrorCounts::default(),
+            errors: Vec::new(),
+            total_blocks: 0,
+            total_apparent_size: 0,
+            dirs: DirLevels::default(),
+            files: RetainedFiles::new(&config.output_config),
+            visit,
+            ignore: None,
+            dev: None,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            dirent_buffer: Vec::with_capacity(DIRENT_BUFFER_SIZE),
+            histogram: config.output_config.histogram.then(Histogram::default),
+            extensions: config.output_config.by_extension.then(ExtensionBreakdown::default),
+            types: config.output_config.by_type.map(|_| ExtensionBreakdown::default()),
+            sniffer: config.output_config.by_type.map(Sniffer::new),
+            sparse_files: config.output_config.sparse.then(Vec::new),
+            mounts: config.output_config.by_mount.then(MountBreakdown::default),
+            empty: config.output_config.empty.then(Vec::new),
+            duplicates: config.output_config.duplicates.map(|_| Vec::new()),
+            count_special: config.traverse_config.include_special,
+            special_files: 0,
+            non_regular: 0,
+            panics: 0,
+            sample: config.performance_config.sample,
+            sample_variance: 0.0,
+        }
+    }
+
+    /// Hand the accumulated statistics over to the walker
+    pub fn take_result(&mut self) -> WorkerResult {
+        if self.special_files > 0 {
+            log::info!(
+                "Worker {} {} {} special files (devices, FIFOs, sockets)",
+                self.id,
+                if self.count_special { "counted" } else { "skipped" },
+                self.special_files
+            );
+        }
+        WorkerResult {
+            dirs_processed: self.dirs_processed,
+            files_processed: self.files_processed,
+            total_blocks: self.total_blocks,
+            total_apparent_size: self.total_apparent_size,
+            error_counts: self.error_counts,
+            errors: std::mem::take(&mut self.errors),
+            dirs: std::mem::take(&mut self.dirs),
+            files: self.files.take(),
+            histogram: self.histogram.take(),
+            extensions: self.extensions.take(),
+            types: self.types.take(),
+            sparse_files: self.sparse_files.take(),
+            mounts: self.mounts.take(),
+            empty: self.empty.take(),
+            duplicates: self.duplicates.take(),
+            non_regular: std::mem::take(&mut self.non_regular),
+            panics: std::mem::take(&mut self.panics),
+            sample_variance: std::mem::take(&mut self.sample_variance),
+            scheduler: None,
+        }
+    }
+
+    /// Process a job, recording its errors. A panic loses the job: the
+    /// statistics of its directory unwind with it, so the worker totals
+    /// it added are rolled back too, and the panic is counted for the scan
+    /// to be reported incomplete. Other jobs are not affected.
+    pub fn process(&mut self, job: &Job) {
+        let processed = self.dirs_processed + self.files_processed;
+        let totals = (
+            self.total_blocks,
+            self.total_apparent_size,
+            self.special_files,
+            self.non_regular,
+            self.sample_variance,
+        );
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.process_job(job)));
+        self.count_entries(job, self.dirs_processed + self.files_processed - processed);
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => self.record_error(err),
+            Err(payload) => {
+                self.panics += 1;
+                (
+                    self.total_blocks,
+                    self.total_apparent_size,
+                    self.special_files,
+                    self.non_regular,
+                    self.sample_variance,
+                ) = totals;
+                // Taken while a directory is read, put back on return only
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if self.dirent_buffer.capacity() == 0 {
+                    self.dirent_buffer = Vec::with_capacity(DIRENT_BUFFER_SIZE);
+                }
+                log::error!(
+                    "Worker {} gave up on {} after an internal error: {}",
+                    self.id,
+                    escape_path(&job.path),
+                    panic_message(payload.as_ref())
+                );
+            }
+        }
+    }
+
+    /// Count a per-entry error and either log it now or keep it for the
+    /// summary. Unless errors are skipped, the walk is cancelled and the
+    /// error kept for `scan` to fail with.
+    fn record_error(&mut self, err: WalkError) {
+        self.error_counts.record(&err);
+        let on_error = self.config.traverse_config.on_error;
+        if on_error != ErrorPolicy::Skip && !self.cancelled.swap(true, Ordering::Relaxed) {
+            log::info!("Worker {} stopping the walk on error (--on-error {:?})", self.id, on_error);
+        }
+        if self.config.performance_config.buffer_errors || on_error == ErrorPolicy::FailFast {
+            self.errors.push(err);
+        } else {
+            log::error!("Worker {} skipping entry: {}", self.id, err);
+        }
+    }
+
+    fn process_job(&mut self, job: &Job) -> Result<(), WalkError> {
+        // Jobs deeper than the prune depth are not walked, not an error
+        if let Some(max) = self.config.traverse_config.prune_depth
+            && job.depth > max
+        {
+            log::trace!("Worker {} pruned {} at depth {} > {}", self.id, escape_path(&job.path), job.depth, max);
+            return Ok(());
+        }
+
+        // Short path if the root path is a file, it is retained as a file entry
+        if !job.is_dir {
+            self.files_processed += 1;
+            let mut stats = DirStats::new(job.depth, job.root);
+            let stat = handle::stat(&job.path).map_err(|err| WalkError::io(&job.path, err))?;
+            self.process_file(&stat, FilePath::Whole(&job.path), 1, job, &mut stats);
+            return Ok(());
+        }
+
+        // Subdirectories are opened relative to their parent while it is still open
+        let parent = job.parent.as_deref().zip(job.path.file_name());
+
+        // The directory inode itself takes up blocks too, like du counts it
+        let mut stats = DirStats::new(job.depth, job.root);
+        // An input path is followed when it is a symlink, like du does
+        // with the paths given on the command line
+        let stat = match parent {
+            Some((parent, name)) => parent.stat_at(name),
+            None if job.depth == 0 => handle::stat(&job.path),
+            None => handle::lstat(&job.path),
+        };
+        match stat {
+            Ok(stat) => {
+                self.dev = Some(stat.dev);
+                stats.time = stat.time(self.config.output_config.time_field);
+                if self.config.traverse_config.regular_only {
+                    self.non_regular += 1;
+                } else {
+                    self.total_blocks += stat.blocks;
+                    self.total_apparent_size += stat.len;
+                    stats.blocks += stat.blocks;
+                    stats.apparent_size += stat.len;
+                    self.count_towards_quota(job.root, stat.blocks, stat.len, || job.path.clone());
+                    if let Some(mounts) = &mut self.mounts {
+                        mounts.record_dir(stat.dev, stat.len, stat.blocks);
+                    }
+                }
+            }
+            Err(err) => {
+                self.dev = job.dev;
+                self.record_error(WalkError::io(&job.path, err));
+            }
+        }
+
+        // Read entries
+        let opened = match parent {
+            Some((parent, name)) => parent.open_at(name),
+            None => DirHandle::open(&job.path),
+        };
+        let dir = match opened {
+            Ok(dir) => Arc::new(dir),
+            Err(err) => {
+                // Still account for the directory's own blocks
+                self.dirs.insert(job.path.clone(), stats);
+                return Err(WalkError::io(&job.path, err));
+            }
+        };
+        if self.config.filter_config.gitignore {
+            self.ignore = IgnoreRules::load(&dir, &job.path, job.ignore.clone());
+        }
+        let has_entries = self.read_entries(&dir, job, &mut stats);
+        if !has_entries && let Some(empty) = &mut self.empty {
+            empty.push(job.path.clone());
+        }
+
+        self.dirs_processed += 1;
+        self.dirs.insert(job.path.clone(), stats);
+        Ok(())
+    }
+
+    /// Read the entries of a directory in large batches with `getdents64`,
+    /// into a buffer reused for every directory. Returns whether the
+    /// directory has any entry, an unreadable one counts as not empty.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn read_entries(&mut self, dir: &Arc<DirHandle>, job: &Job, stats: &mut DirStats) -> bool {
+        let mut buffer = std::mem::take(&mut self.dirent_buffer);
+        let mut entries = RawDir::new(dir.fd(), buffer.spare_capacity_mut());
+        let mut has_entries = false;
+        let mut attempt = 1;
+        while let Some(entry) = entries.next() {
+            match entry {
+                Ok(entry) => {
+                    attempt = 1;
+                    let name = OsStr::from_bytes(entry.file_name().to_bytes());
+                    has_entries |= name != "." && name != "..";
+                    self.process_entry(dir, name, entry.file_type(), entry.ino(), job, stats);
+                }
+                // The buffer is left empty, the next call reads it again
+                Err(err) if handle::is_transient(err) && attempt < handle::TRANSIENT_ATTEMPTS => {
+                    handle::backoff(attempt);
+                    attempt += 1;
+                }
+                Err(err) => {
+                    // Reading further would fail the same way
+                    self.record_error(WalkError::io(&job.path, err.into()));
+                    has_entries = true;
+                    break;
+                }
+            }
+        }
+        self.dirent_buffer = buffer;
+        has_entries
+    }
+
+    /// Read the entries of a directory one at a time. Returns whether the
+    /// directory has any entry, an unreadable one counts as not empty.
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    fn read_entries(&mut self, dir: &Arc<DirHandle>, job: &Job, stats: &mut DirStats) -> bool {
+        let entries = match dir.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.record_error(WalkError::io(&job.path, err));
+                return true;
+            }
+        };
+        let mut has_entries = false;
+        for entry in entries {
+            match entry {
+                Ok(entry) => {
+                    let name = OsStr::from_bytes(entry.file_name().to_bytes());
+                    has_entries |= name != "." && name != "..";
+                    self.process_entry(dir, name, entry.file_type(), entry.ino(), job, stats);
+                }
+                Err(err) => {
+                    self.record_error(WalkError::io(&job.path, err.into()));
+                    has_entries = true;
+                }
+            }
+        }
+        has_entries
+    }
+
+    /// Read the entries of a directory one at a time. Returns whether the
+    /// directory has any entry, an unreadable one counts as not empty.
+    #[cfg(windows)]
+    fn read_entries(&mut self, dir: &Arc<DirHandle>, job: &Job, stats: &mut DirStats) -> bool {
+        let entries = match dir.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.record_error(WalkError::io(&job.path, err));
+                return true;
+            }
+        };
+        let mut has_entries = false;
+        for entry in entries {
+            // `.` and `..` are not listed
+            has_entries = true;
+            match entry.and_then(|entry| Ok((entry.file_name(), entry.file_type()?))) {
+                Ok((name, file_type)) => {
+                    let key = sample::name_key(&name);
+                    self.process_entry(dir, &name, file_type.into(), key, job, stats)
+                }
+                Err(err) => self.record_error(WalkError::io(&job.path, err)),
+            }
+        }
+        has_entries
+    }
+
+    /// Schedule a subdirectory, or count a file in its directory
+    /// statistics. `key` picks the files counted with --sample.
+    fn process_entry(
+        &mut self,
+        dir: &Arc<DirHandle>,
+        name: &OsStr,
+        mut file_type: FileType,
+        key: u64,
+        job: &Job,
+        stats: &mut DirStats,
+    ) {
+        if name == "." || name == ".." {
+            return;
+        }
+
+        // Some filesystems do not report the type in the directory entry
+        let mut stat = None;
+        if file_type == FileType::Unknown {
+            match dir.stat_at(name) {
+                Ok(entry_stat) => {
+                    file_type = entry_stat.file_type;
+                    stat = Some(entry_stat);
+                }
+                // Whiteouts of union mounts are listed with a type of their
+                // own but have no inode, du skips them
+                #[cfg(any(
+                    target_os = "macos",
+                    target_os = "freebsd",
+                    target_os = "dragonfly",
+                    target_os = "netbsd",
+                    target_os = "openbsd"
+                ))]
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+                Err(err) => {
+                    self.record_error(WalkError::io(&job.path.join(name), err));
+                    return;
+                }
+            }
+        }
+
+        let is_dir = file_type == FileType::Directory;
+        if self.is_skipped(&job.path, name, is_dir) {
+            return;
+        }
+
+        if is_dir {
+            // Scheduled by the walker once the directory is read
+            let parent = DirHandle::can_share().then(|| dir.clone());
+            let mut new_job = Job::new(
+                job.path.join(name),
+                parent,
+                job.depth + 1,
+                true,
+                job.root,
+                self.ignore.clone(),
+            );
+            new_job.dev = self.dev;
+            self.new_jobs.push(new_job);
+        } else {
+            self.files_processed += 1;
+            // Files left out of the sample are not even stat'ed
+            let weight = match self.sample.map(|sample| sample.weight(key)) {
+                Some(Some(weight)) => weight,
+                Some(None) => return,
+                None => 1,
+            };
+            match stat.map_or_else(|| dir.stat_at(name), Ok) {
+                Ok(stat) => {
+                    let path = FilePath::Joined(&job.path, name);
+                    self.process_file(&stat, path, weight, job, stats);
+                }
+                Err(err) => self.record_error(WalkError::io(&job.path.join(name), err)),
+            }
+        }
+    }
+
+    /// Count the entries processed for a job towards --max-entries,
+    /// cancelling the walk once the limit is passed
+    fn count_entries(&self, job: &Job, entries: usize) {
+        if let Some(limit) = &self.limits.entries
+            && limit.add(entries as u64, || job.path.clone())
+        {
+            log::info!(
+                "Worker {} stopping the walk at {}, over the --max-entries limit",
+                self.id,
+                escape_path(&job.path)
+            );
+            self.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Count the size of an entry towards the --exceeds limit of its root,
+    /// cancelling the walk once a root goes over it
+    fn count_towards_quota(&self, root: usize, blocks: u64, len: u64, path: impl FnOnce() -> PathBuf) {
+        let Some(quota) = &self.limits.quota else {
+            return;
+        };
+        let size = if self.config.output_config.apparent_size {
+            len
+        } else {
+            blocks_to_bytes(blocks)
+        };
+        if quota.add(root, size, path) {
+            log::info!("Worker {} stopping the walk, a path went over the --exceeds limit", self.id);
+            self.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether an entry is left out by the include/exclude patterns or the
+    /// `.gitignore` rules. Skipped directories are not walked at all.
+    fn is_skipped(&self, dir: &Path, name: &OsStr, is_dir: bool) -> bool {
+        let filter = &self.config.filter_config;
+        if self.ignore.is_none() && !filter.has_patterns() {
+            return false;
+        }
+        let path = dir.join(name);
+        filter.is_excluded(&path)
+            || (!is_dir && !filter.is_included(&path))
+            || self.ignore.as_ref().is_some_and(|ignore| ignore.is_ignored(&path, is_dir))
+    }
+
+    /// Count a file in the statistics of its directory, as `weight` files
+    /// of its size with --sample. `job` is the root file itself or the
+    /// directory the file was found in.
+    fn process_file(&mut self, stat: &FileStat, path: FilePath, weight: u64, job: &Job, stats: &mut DirStats) {
+        // Count a hard-linked file only through the first link seen
+        if stat.nlink > 1
+            && let Some(hard_links) = self.hard_links
+            && !hard_links.first_visit(stat.dev, stat.ino)
+        {
+            return;
+        }
+        if self.config.traverse_config.regular_only && stat.file_type != FileType::RegularFile {
+            self.non_regular += 1;
+            return;
+        }
+        // Files outside the size range, the age window or not owned
+        // by the wanted user and group are left out entirely
+        let size = if self.config.output_config.apparent_size {
+            stat.len
+        } else {
+            blocks_to_bytes(stat.blocks)
+        };
+        if !self.config.filter_config.size_in_range(size)
+            || !self.config.filter_config.mtime_in_range(stat.mtime)
+            || !self.config.filter_config.owner_matches(stat.uid, stat.gid)
+            || stat.file_type == FileType::Symlink
+        {
+            return;
+        }
+        if is_special_file(stat.file_type) {
+            self.special_files += 1;
+            if !self.count_special {
+                return;
+            }
+        }
+
+        if let Some(sample) = &self.sample {
+            self.sample_variance += sample.variance(size);
+        }
+        let (blocks, len) = (stat.blocks.saturating_mul(weight), stat.len.saturating_mul(weight));
+        self.total_blocks += blocks;
+        self.total_apparent_size += len;
+        stats.blocks += blocks;
+        stats.apparent_size += len;
+        stats.files += weight;
+        self.count_towards_quota(job.root, blocks, len, || path.to_path_buf());
+        stats.time = stats.time.max(stat.time(self.config.output_config.time_field));
+        if let Some(histogram) = &mut self.histogram {
+            histogram.record(stat.len, stat.blocks);
+        }
+        if let Some(extensions) = &mut self.extensions {
+            extensions.record(path.name(), stat.len, stat.blocks);
+        }
+        if let Some(types) = &mut self.types
+            && let Some(sniffer) = &mut self.sniffer
+        {
+            // Each worker reads the files it finds, so files are read on
+            // all threads at once
+            let kind = if stat.file_type == FileType::RegularFile {
+                sniffer.classify(&path.to_path_buf(), stat.len)
+            } else {
+                filetypes::SPECIAL
+            };
+            types.record_key(kind, stat.len, stat.blocks);
+        }
+        if let Some(mounts) = &mut self.mounts {
+            mounts.record_file(stat.dev, stat.len, stat.blocks);
+        }
+
+        let depth = match path {
+            FilePath::Whole(_) => job.depth,
+            FilePath::Joined(..) => job.depth + 1,
+        };
+        let entry = || Entry {
+            path: path.to_path_buf(),
+            depth,
+            root: job.root,
+            is_dir: false,
+            blocks: stat.blocks,
+            apparent_size: stat.len,
+            files: 1,
+            dirs: 0,
+            time: stat.time(self.config.output_config.time_field),
+            ino: (stat.nlink > 1).then_some(stat.ino),
+        };
+        if let Some(sparse_files) = &mut self.sparse_files
+            && stat.len > blocks_to_bytes(stat.blocks)
+        {
+            sparse_files.push(entry());
+        }
+        if let Some(empty) = &mut self.empty
+            && stat.len == 0
+            && stat.file_type == FileType::RegularFile
+        {
+            empty.push(path.to_path_buf());
+        }
+        if let Some(duplicates) = &mut self.duplicates
+            && stat.file_type == FileType::RegularFile
+            && self.config.output_config.duplicates.is_some_and(|min| stat.len >= min)
+        {
+            duplicates.push(Candidate {
+                path: path.to_path_buf(),
+                len: stat.len,
+                dev: stat.dev,
+                ino: stat.ino,
+            });
+        }
+        // Every file is streamed to a visitor. Otherwise root files
+        // are always candidates, others only with --all/--files-only
+        let output_config = &self.config.output_config;
+        if let Some(visit) = self.visit {
+            visit(&entry());
+        } else if output_config.shows_files() || depth == 0 {
+            self.files.push(entry(), output_config);
+        }
+    }
+}
+
+/// Path of a file, only joined from its directory and name when an entry
+/// is actually kept
+#[derive(Clone, Copy)]
+enum FilePath<'p> {
+    /// A root path given as is
+    Whole(&'p Path),
+    /// A directory path and the name of the file in it
+    Joined(&'p Path, &'p OsStr),
+}
+
+impl FilePath<'_> {
+    /// The part of the path that names the file itself
+    fn name(&self) -> &Path {
+        match self {
+            FilePath::Whole(path) => path,
+            FilePath::Joined(_, name) => Path::new(name),
+        }
+    }
+
+    fn to_path_buf(self) -> PathBuf {
+        match self {
+            FilePath::Whole(path) => path.to_path_buf(),
+            FilePath::Joined(dir, name) => dir.join(name),
+        }
+    }
+}
+
+/// The message a panic was raised with, when it has one
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Devices, FIFOs and sockets, only counted with --include-special
+fn is_special_file(file_type: FileType) -> bool {
+    matches!(
+        file_type,
+        FileType::BlockDevice | FileType::CharacterDevice | FileType::Fifo | FileType::Socket
+    )
+}
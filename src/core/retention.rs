@@ -0,0 +1,95 @@
+use crate::config::OutputConfig;
+use crate::core::aggregate::Entry;
+use crate::output::is_displayed;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Entry ranked by size, larger first and ties in path order
+pub struct BySize {
+    pub size: u64,
+    pub entry: Entry,
+}
+
+impl Ord for BySize {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.size
+            .cmp(&other.size)
+            .then_with(|| other.entry.path.cmp(&self.entry.path))
+    }
+}
+
+impl PartialOrd for BySize {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for BySize {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BySize {}
+
+/// The file entries a worker keeps for display.
+///
+/// Files that can never be displayed (below --threshold, past
+/// --max-depth...) are dropped as soon as they are counted, and with
+/// --top only the N largest candidates are kept. Memory then follows what
+/// is displayed instead of the number of files in the tree.
+pub enum RetainedFiles {
+    /// Every file, for the tree exports and --save which need them all
+    All(Vec<Entry>),
+    /// Every file that can be displayed
+    Displayed(Vec<Entry>),
+    /// The N largest files that can be displayed, the smallest on top
+    Top(usize, BinaryHeap<Reverse<BySize>>),
+}
+
+impl RetainedFiles {
+    pub fn new(config: &OutputConfig) -> Self {
+        if config.exports_tree() || config.save.is_some() {
+            RetainedFiles::All(Vec::new())
+        } else if let Some(n) = config.top {
+            RetainedFiles::Top(n, BinaryHeap::new())
+        } else {
+            RetainedFiles::Displayed(Vec::new())
+        }
+    }
+
+    /// Keep a file entry if it can make it to the output
+    pub fn push(&mut self, entry: Entry, config: &OutputConfig) {
+        match self {
+            RetainedFiles::All(files) => files.push(entry),
+            RetainedFiles::Displayed(files) => {
+                if is_displayed(&entry, config) {
+                    files.push(entry);
+                }
+            }
+            RetainedFiles::Top(n, heap) => {
+                if is_displayed(&entry, config) {
+                    heap.push(Reverse(BySize {
+                        size: config.size_of(&entry),
+                        entry,
+                    }));
+                    if heap.len() > *n {
+                        heap.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hand the kept entries over, leaving the same policy empty
+    pub fn take(&mut self) -> Vec<Entry> {
+        match self {
+            RetainedFiles::All(files) | RetainedFiles::Displayed(files) => std::mem::take(files),
+            RetainedFiles::Top(_, heap) => std::mem::take(heap)
+                .into_vec()
+                .into_iter()
+                .map(|Reverse(ranked)| ranked.entry)
+                .collect(),
+        }
+    }
+}
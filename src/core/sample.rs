@@ -0,0 +1,85 @@
+use std::ffi::OsStr;
+
+/// Files counted with --sample, the others are never stat'ed.
+///
+/// Files are picked by a hash of their inode number, so a run picks the
+/// same files as the last one and every link to a file is picked or none
+/// is. Each file picked stands for about `1 / rate` files, its size is
+/// counted that many times.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// Fraction of the files picked, in (0, 1]
+    rate: f64,
+}
+
+impl Sample {
+    pub fn new(rate: f64) -> Self {
+        Self { rate }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// How many files a file stands for, `None` when it is left out.
+    /// `key` identifies the file: its inode number, or a hash of its name
+    /// where there is none.
+    pub fn weight(&self, key: u64) -> Option<u64> {
+        // Uniform in [0, 1)
+        let draw = (mix(key) >> 11) as f64 / (1u64 << 53) as f64;
+        if draw >= self.rate {
+            return None;
+        }
+        // Rounded up or down at random, so that a file stands for exactly
+        // `1 / rate` files on average and the estimate is unbiased
+        let scale = 1.0 / self.rate;
+        let whole = scale.floor();
+        let round_up = draw / self.rate < scale - whole;
+        Some(whole as u64 + u64::from(round_up))
+    }
+
+    /// What a file of `size` picked adds to the variance of the estimated
+    /// total
+    pub fn variance(&self, size: u64) -> f64 {
+        (1.0 - self.rate) / (self.rate * self.rate) * (size as f64).powi(2)
+    }
+}
+
+/// An estimated total and how far off it may be
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    /// Fraction of the files counted
+    pub rate: f64,
+    /// Half the width of the 95% confidence interval of the total, in
+    /// bytes
+    pub margin: u64,
+}
+
+impl Estimate {
+    /// z-score of a two-sided 95% confidence interval
+    const Z_95: f64 = 1.96;
+
+    pub fn new(sample: Sample, variance: f64) -> Self {
+        Self {
+            rate: sample.rate(),
+            margin: (Self::Z_95 * variance.sqrt()).round() as u64,
+        }
+    }
+}
+
+/// Key of a file without an inode number: the 64-bit FNV-1a hash of its
+/// name, the same on every run and with every Rust version, unlike the
+/// standard library's hasher
+pub fn name_key(name: &OsStr) -> u64 {
+    name.as_encoded_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// The SplitMix64 finalizer, spreads close inode numbers over the whole
+/// range
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
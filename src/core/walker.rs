@@ -1,31 +1,332 @@
 use std::{
+    panic::{self, AssertUnwindSafe},
     path::PathBuf,
-    sync::{Arc, atomic::AtomicI64},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+    },
 };
 
-use crate::core::worker::{Job, WalkWorker, WorkerResult};
+use crate::config::Config;
+use crate::core::aggregate::{self, DirLevels, Entry, blocks_to_bytes};
+use crate::core::devices::{DevicePools, Pool};
+use crate::core::duplicates::{self, Candidate, DuplicateSet};
+use crate::core::extensions::ExtensionBreakdown;
+use crate::core::hardlinks::HardLinkCache;
+use crate::core::histogram::Histogram;
+use crate::core::mounts::MountBreakdown;
+use crate::core::processor::{JobProcessor, panic_message};
+use crate::core::limits::{EntryLimit, Exceeded, Limits, Quota};
+use crate::core::worker::{self, Job, Parking, Router, SchedulerStats, WalkWorker, WorkerResult};
+use crate::error::{ErrorCounts, WalkError};
 use anyhow::anyhow;
 use crossbeam_deque::{Injector, Stealer, Worker};
-use crossbeam_utils::thread::ScopedJoinHandle;
+use crossbeam_utils::thread::{Scope, ScopedJoinHandle};
+
+/// Result of a walk: aggregated entries and the grand totals
+#[derive(Default)]
+pub struct WalkResult {
+    pub entries: Vec<Entry>,
+    /// Number of directories and files visited by the walk
+    pub dirs_processed: usize,
+    pub files_processed: usize,
+    pub total_blocks: u64,
+    pub total_apparent_size: u64,
+    pub error_counts: ErrorCounts,
+    /// Errors buffered by the workers with --buffer-errors
+    pub errors: Vec<WalkError>,
+    /// File size histogram, only with --histogram
+    pub histogram: Option<Histogram>,
+    /// Totals per file extension, only with --by-extension
+    pub extensions: Option<ExtensionBreakdown>,
+    /// Totals per content type, only with --by-type
+    pub types: Option<ExtensionBreakdown>,
+    /// Sparse files, the largest gap between apparent and allocated size
+    /// first, only with --sparse
+    pub sparse_files: Option<Vec<Entry>>,
+    /// Totals per filesystem, only with --by-mount
+    pub mounts: Option<MountBreakdown>,
+    /// Empty directories and zero-byte files in path order, only with --empty
+    pub empty: Option<Vec<PathBuf>>,
+    /// Sets of files with identical content, only with --duplicates
+    pub duplicates: Option<Vec<DuplicateSet>>,
+    /// Entries that are not regular files, skipped with --regular-only
+    pub non_regular: u64,
+    /// Jobs cut short by a panic, the rest of their directory is missing
+    pub panics: usize,
+    /// Workers lost to a panic with everything they counted, the walk is
+    /// then cancelled
+    pub lost_workers: usize,
+    /// The input path that went over the limit first, with --exceeds. The
+    /// walk is then cancelled.
+    pub exceeded: Option<Exceeded>,
+    /// The directory that took the walk past --max-entries, the walk is
+    /// then cancelled
+    pub max_entries_reached: Option<PathBuf>,
+    /// Variance of the estimated total, only with --sample
+    pub sample_variance: f64,
+    /// Scheduling of each worker, in worker order, empty when walking on
+    /// a single thread
+    pub workers: Vec<SchedulerStats>,
+    /// Whether the walk was cancelled before visiting everything
+    pub cancelled: bool,
+}
+
+impl WalkResult {
+    /// Grand total in bytes: apparent size or disk usage
+    pub fn total_size(&self, apparent: bool) -> u64 {
+        if apparent {
+            self.total_apparent_size
+        } else {
+            blocks_to_bytes(self.total_blocks)
+        }
+    }
+
+    /// Merge the results of the workers and aggregate per-directory totals.
+    /// With a visitor the aggregated entries are handed to it instead of
+    /// being kept in `entries`.
+    fn from_worker_results(
+        results: impl IntoIterator<Item = WorkerResult>,
+        visit: Option<&Visitor>,
+        threads: usize,
+    ) -> Self {
+        let mut dirs_processed: usize = 0;
+        let mut files_processed: usize = 0;
+        let mut total_blocks: u64 = 0;
+        let mut total_apparent_size: u64 = 0;
+        let mut error_counts = ErrorCounts::default();
+        let mut errors: Vec<WalkError> = Vec::new();
+        let mut dirs = DirLevels::default();
+        let mut files: Vec<Entry> = Vec::new();
+        let mut histogram: Option<Histogram> = None;
+        let mut extensions: Option<ExtensionBreakdown> = None;
+        let mut types: Option<ExtensionBreakdown> = None;
+        let mut sparse_files: Option<Vec<Entry>> = None;
+        let mut mounts: Option<MountBreakdown> = None;
+        let mut empty: Option<Vec<PathBuf>> = None;
+        let mut candidates: Option<Vec<Candidate>> = None;
+        let mut non_regular: u64 = 0;
+        let mut panics: usize = 0;
+        let mut sample_variance: f64 = 0.0;
+        let mut workers: Vec<SchedulerStats> = Vec::new();
+        for worker_result in results {
+            dirs_processed += worker_result.dirs_processed;
+            files_processed += worker_result.files_processed;
+            total_blocks = total_blocks.saturating_add(worker_result.total_blocks);
+            total_apparent_size = total_apparent_size.saturating_add(worker_result.total_apparent_size);
+            error_counts.merge(&worker_result.error_counts);
+            errors.extend(worker_result.errors);
+            dirs.merge(worker_result.dirs);
+            files.extend(worker_result.files);
+            if let Some(other) = worker_result.histogram {
+                histogram.get_or_insert_default().merge(&other);
+            }
+            if let Some(other) = worker_result.extensions {
+                extensions.get_or_insert_default().merge(other);
+            }
+            if let Some(other) = worker_result.types {
+                types.get_or_insert_default().merge(other);
+            }
+            if let Some(other) = worker_result.sparse_files {
+                sparse_files.get_or_insert_default().extend(other);
+            }
+            if let Some(other) = worker_result.mounts {
+                mounts.get_or_insert_default().merge(other);
+            }
+            if let Some(other) = worker_result.empty {
+                empty.get_or_insert_default().extend(other);
+            }
+            if let Some(other) = worker_result.duplicates {
+                candidates.get_or_insert_default().extend(other);
+            }
+            non_regular += worker_result.non_regular;
+            panics += worker_result.panics;
+            sample_variance += worker_result.sample_variance;
+            workers.extend(worker_result.scheduler);
+        }
+        if non_regular > 0 {
+            log::info!("Skipped {non_regular} entries that are not regular files (--regular-only)");
+        }
+        if let Some(sparse_files) = &mut sparse_files {
+            sparse_files.sort_by(|a, b| {
+                b.unallocated()
+                    .cmp(&a.unallocated())
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+        }
+        if let Some(empty) = &mut empty {
+            empty.sort();
+        }
+        let duplicates = candidates.map(|candidates| duplicates::find_duplicates(candidates, threads));
+        let mut entries = aggregate::aggregate(dirs, files, threads);
+        if let Some(visit) = visit {
+            entries.drain(..).for_each(|entry| visit(&entry));
+        }
+        Self {
+            entries,
+            dirs_processed,
+            files_processed,
+            total_blocks,
+            total_apparent_size,
+            error_counts,
+            errors,
+            histogram,
+            extensions,
+            types,
+            sparse_files,
+            mounts,
+            empty,
+            duplicates,
+            non_regular,
+            panics,
+            lost_workers: 0,
+            exceeded: None,
+            max_entries_reached: None,
+            sample_variance,
+            workers,
+            cancelled: false,
+        }
+    }
+}
+
+/// Callback receiving entries as they are finalized, possibly from
+/// several worker threads at once
+pub type Visitor<'v> = dyn Fn(&Entry) + Sync + 'v;
+
+/// A filesystem traversal strategy
+pub trait Walker {
+    /// Walk the trees under all `roots` and aggregate per-directory totals.
+    /// Entries are tagged with the index of the root they belong to.
+    fn walk(&self, roots: &[PathBuf]) -> anyhow::Result<WalkResult> {
+        self.walk_with(roots, None)
+    }
+
+    /// Like `walk`, but entries are streamed to `visit` instead of being
+    /// collected: files as soon as they are counted, directories once the
+    /// walk is over and their subtree totals are rolled up, deepest first.
+    fn walk_with(&self, roots: &[PathBuf], visit: Option<&Visitor>) -> anyhow::Result<WalkResult>;
+
+    /// Flag that cancels a running walk when set, from any thread. The
+    /// walk stops taking new jobs and returns what it aggregated so far.
+    fn cancel_flag(&self) -> Arc<AtomicBool>;
+}
+
+/// Hard links are deduplicated across all roots unless counted explicitly
+fn hard_link_cache(config: &Config) -> Option<HardLinkCache> {
+    (!config.traverse_config.count_hard_links && config.performance_config.use_cache)
+        .then(|| HardLinkCache::new(config.performance_config.cache_size_bytes))
+}
+
+/// Record in the result of a walk which of its limits were reached
+fn record_limits(result: &mut WalkResult, limits: Limits) {
+    result.exceeded = limits.quota.and_then(Quota::into_exceeded);
+    result.max_entries_reached = limits.entries.and_then(EntryLimit::into_exceeded);
+}
+
+/// One job per root, root files are processed as files. A root that is
+/// a symlink is followed, whatever --dereference says, so a link to a
+/// directory is walked as that directory.
+fn root_jobs(roots: &[PathBuf]) -> Vec<Job> {
+    roots
+        .iter()
+        .enumerate()
+        .map(|(index, root)| {
+            let mut root_job = Job::new(root.clone(), None, 0, true, index, None);
+            if let Ok(metadata) = root.metadata() {
+                root_job.is_dir = metadata.is_dir();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::MetadataExt;
+                    root_job.dev = Some(metadata.dev());
+                }
+            }
+            root_job
+        })
+        .collect()
+}
+
+/// Walks on the calling thread with a plain stack, no queues or atomics.
+/// Entries are visited in a deterministic order, which makes it suited to
+/// small inputs and to debugging.
+pub struct SingleThreaded {
+    config: Config,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl SingleThreaded {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Walker for SingleThreaded {
+    fn walk_with(&self, roots: &[PathBuf], visit: Option<&Visitor>) -> anyhow::Result<WalkResult> {
+        let hard_links = hard_link_cache(&self.config);
+        let limits = Limits::new(&self.config, roots.len());
+        let mut processor = JobProcessor::new(
+            0,
+            &self.config,
+            hard_links.as_ref(),
+            &limits,
+            visit,
+            &self.cancelled,
+        );
+
+        // Process the roots in order, each tree depth-first
+        let mut stack = root_jobs(roots);
+        stack.reverse();
+        while let Some(job) = stack.pop() {
+            if self.cancelled.load(Ordering::Relaxed) {
+                log::info!("Walk cancelled with {} jobs left", stack.len() + 1);
+                break;
+            }
+            processor.process(&job);
+            stack.extend(processor.new_jobs.drain(..).rev());
+        }
+
+        log::info!(
+            "Walk finished: dirs={}, files={}, errors={}",
+            processor.dirs_processed,
+            processor.files_processed,
+            processor.error_counts.total()
+        );
+        let mut result = WalkResult::from_worker_results([processor.take_result()], visit, 1);
+        record_limits(&mut result, limits);
+        result.cancelled = self.cancelled.load(Ordering::Relaxed);
+        Ok(result)
+    }
+
+    fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+}
 
 pub struct Multithreaded {
     num_threads: usize,
-    follow_symlinks: bool,
-    max_depth: Option<usize>,
-    // _min_depth: Option<usize>,
+    config: Config,
+    cancelled: Arc<AtomicBool>,
 }
 
 impl Multithreaded {
-    pub fn new(num_threads: usize) -> Self {
+    pub fn new(config: &Config) -> Self {
         Self {
-            num_threads,
-            follow_symlinks: false,
-            max_depth: None,
+            num_threads: config.performance_config.threads,
+            config: config.clone(),
+            cancelled: Arc::new(AtomicBool::new(false)),
         }
     }
+}
 
-    pub fn walk(&self, root: PathBuf) -> anyhow::Result<()> {
-        let mut total_blocks: u64 = 0;
+impl Walker for Multithreaded {
+    fn walk_with(&self, roots: &[PathBuf], visit: Option<&Visitor>) -> anyhow::Result<WalkResult> {
+        if let Some(threads_per_device) = self.config.performance_config.threads_per_device {
+            return self.walk_per_device(roots, visit, threads_per_device);
+        }
+        let mut worker_results: Vec<WorkerResult> = Vec::with_capacity(self.num_threads);
+        let mut lost_workers: usize = 0;
         // Global work queue
         let global_injector = Arc::new(Injector::<Job>::new());
 
@@ -43,58 +344,210 @@ impl Multithreaded {
         }
 
         let stealers = Arc::new(stealers);
+        let parking = Arc::new(Parking::default());
 
-        let global_job_counter = Arc::new(AtomicI64::new(1));
+        let global_job_counter = Arc::new(AtomicI64::new(roots.len() as i64));
 
-        // Seed global queue with a root job
-        let mut root_job = Job::new(root.clone(), None, 0, true);
-        if let Ok(metadata) = root.symlink_metadata() {
-            if metadata.is_file() {
-                root_job.is_dir = false;
-            }
+        let hard_links = hard_link_cache(&self.config);
+        let limits = Limits::new(&self.config, roots.len());
+
+        // Seed global queue with one job per root
+        for root_job in root_jobs(roots) {
+            global_injector.push(root_job);
         }
-        global_injector.push(root_job);
 
         // Spawn workers
         let result = crossbeam_utils::thread::scope(|s| {
-            let mut handles: Vec<ScopedJoinHandle<'_, anyhow::Result<WorkerResult>>> = Vec::new();
+            let mut handles: Vec<ScopedJoinHandle<'_, std::thread::Result<anyhow::Result<WorkerResult>>>> = Vec::new();
             for (id, worker) in workers.into_iter().enumerate() {
                 let mut walk_walker = WalkWorker::new(
                     id,
                     worker,
                     stealers.clone(),
                     global_injector.clone(),
-                    self.num_threads,
-                    self.follow_symlinks,
-                    self.max_depth,
+                    parking.clone(),
+                    &self.config,
+                    JobProcessor::new(
+                        id,
+                        &self.config,
+                        hard_links.as_ref(),
+                        &limits,
+                        visit,
+                        &self.cancelled,
+                    ),
                 );
                 let gjc_clone = global_job_counter.clone();
-                let worker_handle = s
-                    .spawn(move |_| walk_walker.run_loop(gjc_clone));
+                let cancelled = &self.cancelled;
+                let worker_handle = s.spawn(move |_| {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| walk_walker.run_loop(gjc_clone, cancelled)));
+                    if result.is_err() {
+                        // Its jobs would never be done, the other workers
+                        // would wait for them forever
+                        cancelled.store(true, Ordering::Relaxed);
+                    }
+                    result
+                });
                 handles.push(worker_handle);
             }
 
             // Wait for all workers and collect errors
             for handle in handles {
                 match handle.join() {
-                    Ok(ok) => {
-                        if let Ok(worker_result) = ok {
-                            total_blocks += worker_result.total_blocks;
-                        } else {
-                            log::warn!("Failed to get worker result");
-                        }
+                    Ok(Ok(Ok(worker_result))) => worker_results.push(worker_result),
+                    Ok(Ok(Err(_))) => log::warn!("Failed to get worker result"),
+                    Ok(Err(payload)) => {
+                        lost_workers += 1;
+                        log::error!("Worker thread panicked: {}", panic_message(payload.as_ref()));
                     }
                     Err(err) => {
-                        log::warn!("Worker thread panicked: {:?}", err);
+                        lost_workers += 1;
+                        log::error!("Worker thread panicked: {:?}", err);
                     }
                 }
             }
         });
-        println!(
-            "Total size: {}",
-            humansize::format_size(total_blocks * 512, humansize::DECIMAL)
-        );
         result.map_err(|e| anyhow!("Thread scope execution failed: {:?}", e))?;
-        Ok(())
+        let mut result = WalkResult::from_worker_results(worker_results, visit, self.num_threads);
+        result.lost_workers = lost_workers;
+        record_limits(&mut result, limits);
+        result.cancelled = self.cancelled.load(Ordering::Relaxed);
+        worker::log_imbalance(&result.workers);
+        Ok(result)
+    }
+
+    fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+}
+
+impl Multithreaded {
+    /// Walk with a pool of `threads_per_device` workers per device, each
+    /// with its own queues. A job is processed by the pool of the device of
+    /// its parent directory, so a mount point itself is read by the pool
+    /// it was found in and its entries by its own.
+    fn walk_per_device(
+        &self,
+        roots: &[PathBuf],
+        visit: Option<&Visitor>,
+        threads_per_device: usize,
+    ) -> anyhow::Result<WalkResult> {
+        let hard_links = hard_link_cache(&self.config);
+        let limits = Limits::new(&self.config, roots.len());
+        let walk = PoolWalk {
+            config: &self.config,
+            hard_links: hard_links.as_ref(),
+            limits: &limits,
+            visit,
+            cancelled: &self.cancelled,
+            pools: DevicePools::new(threads_per_device),
+            next_id: AtomicUsize::new(0),
+            results: Mutex::new(Vec::new()),
+            lost_workers: AtomicUsize::new(0),
+        };
+        // Pools spawn the workers of the devices they find, the scope
+        // waits for all of them
+        crossbeam_utils::thread::scope(|s| {
+            for root_job in root_jobs(roots) {
+                walk.route(s, root_job);
+            }
+        })
+        .map_err(|e| anyhow!("Thread scope execution failed: {:?}", e))?;
+
+        let mut worker_results = walk.results.into_inner().unwrap_or_else(PoisonError::into_inner);
+        worker_results.sort_by_key(|(id, _)| *id);
+        log::info!("Walked with {} workers", worker_results.len());
+        let worker_results = worker_results.into_iter().map(|(_, result)| result);
+        let mut result = WalkResult::from_worker_results(worker_results, visit, self.num_threads);
+        result.lost_workers = walk.lost_workers.into_inner();
+        record_limits(&mut result, limits);
+        result.cancelled = self.cancelled.load(Ordering::Relaxed);
+        worker::log_imbalance(&result.workers);
+        Ok(result)
+    }
+}
+
+/// State shared by the workers of every device pool of a walk
+struct PoolWalk<'env> {
+    config: &'env Config,
+    hard_links: Option<&'env HardLinkCache>,
+    limits: &'env Limits,
+    visit: Option<&'env Visitor<'env>>,
+    cancelled: &'env AtomicBool,
+    pools: DevicePools,
+    /// Id of the next worker spawned
+    next_id: AtomicUsize,
+    /// Results of the finished workers, with their id
+    results: Mutex<Vec<(usize, WorkerResult)>>,
+    /// Workers lost to a panic
+    lost_workers: AtomicUsize,
+}
+
+impl<'env> PoolWalk<'env> {
+    /// Queue a job on the pool of its device, spawning the pool's workers
+    /// when the device has none
+    fn route(&'env self, scope: &Scope<'env>, job: Job) {
+        let Some((pool, workers)) = self.pools.push(job.dev.unwrap_or_default(), job) else {
+            return;
+        };
+        for (slot, worker) in workers.into_iter().enumerate() {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let pool = pool.clone();
+            scope.spawn(move |scope| {
+                let processor =
+                    JobProcessor::new(id, self.config, self.hard_links, self.limits, self.visit, self.cancelled);
+                let router = PoolRouter {
+                    walk: self,
+                    scope,
+                    pool: pool.clone(),
+                };
+                let mut walk_worker = WalkWorker::new(
+                    id,
+                    worker,
+                    pool.stealers.clone(),
+                    pool.injector.clone(),
+                    pool.parking.clone(),
+                    self.config,
+                    processor,
+                )
+                .with_router(slot, Box::new(router));
+                let job_counter = pool.job_counter.clone();
+                match panic::catch_unwind(AssertUnwindSafe(|| walk_worker.run_loop(job_counter, self.cancelled))) {
+                    Ok(Ok(worker_result)) => self
+                        .results
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push((id, worker_result)),
+                    Ok(Err(_)) => log::warn!("Failed to get worker result"),
+                    Err(payload) => {
+                        self.lost_workers.fetch_add(1, Ordering::Relaxed);
+                        // Its jobs would never be done, the other workers
+                        // of its pool would wait for them forever
+                        self.cancelled.store(true, Ordering::Relaxed);
+                        log::error!("Worker thread panicked: {}", panic_message(payload.as_ref()));
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Routes the jobs of a pool's workers to the pools of other devices
+struct PoolRouter<'s, 'env> {
+    walk: &'env PoolWalk<'env>,
+    scope: &'s Scope<'env>,
+    pool: Arc<Pool>,
+}
+
+impl Router for PoolRouter<'_, '_> {
+    fn device(&self) -> u64 {
+        self.pool.dev
+    }
+
+    fn route(&self, job: Job) {
+        self.walk.route(self.scope, job);
+    }
+
+    fn retire(&self) -> bool {
+        self.walk.pools.retire(&self.pool)
     }
 }
@@ -1,50 +1,194 @@
+use crate::config::{Config, IdleBackoff};
+use crate::core::aggregate::{DirLevels, Entry};
+use crate::core::duplicates::Candidate;
+use crate::core::extensions::ExtensionBreakdown;
+use crate::core::gitignore::IgnoreRules;
+use crate::core::handle::DirHandle;
+use crate::core::histogram::Histogram;
+use crate::core::mounts::MountBreakdown;
+use crate::core::processor::JobProcessor;
+use crate::error::{ErrorCounts, WalkError};
+use crate::utils::escape_path;
 use crossbeam_deque::{Injector, Steal, Stealer, Worker};
 use fastrace::prelude::*;
 use std::{
-    fs::{self},
-    os::unix::fs::{FileTypeExt, MetadataExt},
     path::PathBuf,
     sync::{
-        Arc,
-        atomic::{AtomicI64, Ordering},
+        Arc, Condvar, Mutex, PoisonError,
+        atomic::{self, AtomicBool, AtomicI64, AtomicUsize, Ordering},
     },
+    time::Duration,
 };
 
 /// A directory path with its depth relative to the root item
 pub struct Job {
     pub path: PathBuf,
-    pub parent: Option<PathBuf>,
+    /// The open parent directory, `None` for the roots or when too many
+    /// directories are open already
+    pub parent: Option<Arc<DirHandle>>,
     pub depth: usize,
     pub is_dir: bool,
+    /// Index of the input path this job descends from
+    pub root: usize,
+    /// `.gitignore` rules in effect in the parent directory, with --gitignore
+    pub ignore: Option<Arc<IgnoreRules>>,
+    /// Device of the parent directory, of the path itself for the roots,
+    /// `None` when unknown
+    pub dev: Option<u64>,
 }
 
 pub struct WorkerResult {
+    pub dirs_processed: usize,
+    pub files_processed: usize,
     pub total_blocks: u64,
+    pub total_apparent_size: u64,
+    pub error_counts: ErrorCounts,
+    /// Errors kept for the final summary, only with --buffer-errors
+    pub errors: Vec<WalkError>,
+    pub dirs: DirLevels,
+    pub files: Vec<Entry>,
+    pub histogram: Option<Histogram>,
+    pub extensions: Option<ExtensionBreakdown>,
+    pub types: Option<ExtensionBreakdown>,
+    pub sparse_files: Option<Vec<Entry>>,
+    pub mounts: Option<MountBreakdown>,
+    pub empty: Option<Vec<PathBuf>>,
+    pub duplicates: Option<Vec<Candidate>>,
+    /// Entries that are not regular files, skipped with --regular-only
+    pub non_regular: u64,
+    /// Jobs cut short by a panic
+    pub panics: usize,
+    /// Variance of the estimated total, only with --sample
+    pub sample_variance: f64,
+    /// How the worker got its jobs, `None` without a scheduler
+    pub scheduler: Option<SchedulerStats>,
 }
 
-impl WorkerResult {
-    pub fn new(worker: &WalkWorker) -> Self {
-        Self {
-            total_blocks: worker.total_blocks,
-        }
+/// Where a worker found its jobs and how long it waited for them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedulerStats {
+    pub jobs_processed: usize,
+    /// Batches taken from the global queue
+    pub global_steals: usize,
+    /// Jobs taken from the local queues of other workers
+    pub victim_steals: usize,
+    /// Rounds spent finding no work anywhere
+    pub idle_cycles: usize,
+    /// Jobs pushed to the global queue because the local queue was full
+    pub jobs_redistributed: usize,
+    /// Times the worker parked, waiting to be woken by new jobs
+    pub parks: usize,
+}
+
+/// Jobs per worker past this multiple of the mean are logged as an imbalance
+const IMBALANCE_RATIO: f64 = 2.0;
+
+/// Jobs under which a walk is too small for its balance to matter
+const IMBALANCE_MIN_JOBS: usize = 100;
+
+/// Minimum, maximum and mean number of jobs processed per worker
+pub fn jobs_spread(workers: &[SchedulerStats]) -> Option<(usize, usize, f64)> {
+    let jobs = workers.iter().map(|stats| stats.jobs_processed);
+    let min = jobs.clone().min()?;
+    let max = jobs.clone().max()?;
+    let mean = jobs.sum::<usize>() as f64 / workers.len() as f64;
+    Some((min, max, mean))
+}
+
+/// Log when a worker processed far more jobs than the others, a sign the
+/// work stealing did not spread the walk
+pub fn log_imbalance(workers: &[SchedulerStats]) {
+    let total: usize = workers.iter().map(|stats| stats.jobs_processed).sum();
+    if let Some((min, max, mean)) = jobs_spread(workers)
+        && total >= IMBALANCE_MIN_JOBS
+        && max as f64 > IMBALANCE_RATIO * mean
+    {
+        log::debug!("Load imbalance across {} workers: jobs min={min}, max={max}, mean={mean:.1}", workers.len());
     }
 }
 
 impl Job {
-    pub fn new(path: PathBuf, parent: Option<PathBuf>, depth: usize, is_dir: bool) -> Self {
+    pub fn new(
+        path: PathBuf,
+        parent: Option<Arc<DirHandle>>,
+        depth: usize,
+        is_dir: bool,
+        root: usize,
+        ignore: Option<Arc<IgnoreRules>>,
+    ) -> Self {
         Self {
             path,
             parent,
             depth,
             is_dir,
+            root,
+            ignore,
+            dev: None,
         }
     }
 }
 
+/// Where idle workers wait for jobs instead of polling the queues.
+///
+/// A worker parks once its idle backoff is over, and is woken when jobs
+/// are queued or the walk is over. Publishers only take the lock when a
+/// worker is parked, so a busy walk never touches it.
+#[derive(Default)]
+pub struct Parking {
+    /// Number of workers parked or about to park
+    parked: AtomicUsize,
+    lock: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl Parking {
+    /// Wake every parked worker, after jobs were queued or the last one
+    /// was processed
+    pub fn unpark_all(&self) {
+        // Pairs with the fence in `park`: either the worker sees the new
+        // jobs before waiting, or it is counted here and woken
+        atomic::fence(Ordering::SeqCst);
+        if self.parked.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+            self.wakeup.notify_all();
+        }
+    }
+
+    /// Wait until woken or `timeout` has passed, unless `ready` finds
+    /// something to do once the worker is counted as parked. The timeout
+    /// bounds how late a parked worker notices a cancelled walk.
+    fn park(&self, timeout: Duration, ready: impl Fn() -> bool) {
+        let guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.parked.fetch_add(1, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+        if !ready() {
+            drop(self.wakeup.wait_timeout(guard, timeout));
+        }
+        self.parked.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Where the worker of a device pool sends the jobs found on other
+/// devices, with --threads-per-device
+pub trait Router {
+    /// Device of the worker's pool
+    fn device(&self) -> u64;
+
+    /// Queue a job on the pool of its device
+    fn route(&self, job: Job);
+
+    /// Called when the worker's pool has no job left, whether its workers
+    /// can stop
+    fn retire(&self) -> bool;
+}
+
 /// Worker state
-pub struct WalkWorker {
+pub struct WalkWorker<'a> {
     id: usize,
 
+    /// Index of the worker's own queue in `stealers`
+    slot: usize,
+
     /// Internal crossbeam worker
     inner: Worker<Job>,
 
@@ -54,80 +198,101 @@ pub struct WalkWorker {
     /// Shared vector of crossbeam stealers
     stealers: Arc<Vec<Stealer<Job>>>,
 
+    /// Where the worker waits when it runs out of jobs, shared with the
+    /// workers stealing from the same queues
+    parking: Arc<Parking>,
+
     /// Configuration
-    num_workers: usize,
-    follow_symlinks: bool,
-    max_depth: Option<usize>,
-
-    /// Local work delta (work produced - work consumed)
-    /// This is what has to be synced globally when idle.
-    /// Syncing means adding to global_count the local_delta
-    /// and setting the local_delta to zero.
-    /// The global counter is zero only when each worker has no net work coming out of it.
-    local_work_delta: i64,
-
-    /// Statistics
-    dirs_processed: usize,
-    files_processed: usize,
-    errors_count: usize,
-
-    /// Data that can be calculated walking
-    total_blocks: u64,
+    config: &'a Config,
+
+    /// Processes the jobs and accumulates the statistics. The directory
+    /// jobs it produces are counted in the global job counter before
+    /// being pushed, see `publish_jobs`.
+    processor: JobProcessor<'a>,
+
+    /// Where jobs were found, reported with the result
+    stats: SchedulerStats,
+
+    /// Maximum number of jobs stolen from the global queue at once
+    batch_size: usize,
+
+    /// How to wait when no job is found
+    idle: IdleBackoff,
+
+    /// Sends the jobs of other devices to their pool, with
+    /// --threads-per-device
+    router: Option<Box<dyn Router + Send + 'a>>,
 }
 
-impl WalkWorker {
+impl<'a> WalkWorker<'a> {
     pub fn new(
         id: usize,
         inner: Worker<Job>,
         stealers: Arc<Vec<Stealer<Job>>>,
         injector: Arc<Injector<Job>>,
-        num_threads: usize,
-        follow_symlinks: bool,
-        max_depth: Option<usize>,
+        parking: Arc<Parking>,
+        config: &'a Config,
+        processor: JobProcessor<'a>,
     ) -> Self {
         Self {
             id,
+            slot: id,
             inner,
             injector,
             stealers,
-            num_workers: num_threads,
-            follow_symlinks,
-            max_depth,
-            local_work_delta: 0,
-            dirs_processed: 0,
-            files_processed: 0,
-            errors_count: 0,
-            total_blocks: 0,
+            parking,
+            config,
+            processor,
+            stats: SchedulerStats::default(),
+            batch_size: config.performance_config.batch_size,
+            idle: config.performance_config.idle,
+            router: None,
         }
     }
 
+    /// Make the worker the `slot`th of a device pool: `stealers` and the
+    /// injector are the pool's, and jobs of other devices go to `router`
+    pub fn with_router(mut self, slot: usize, router: Box<dyn Router + Send + 'a>) -> Self {
+        self.slot = slot;
+        self.router = Some(router);
+        self
+    }
+
     /// Try to get work: local queue -> global queue -> steal from victims
-    fn find_work(&self) -> Option<Job> {
+    fn find_work(&mut self) -> Option<Job> {
         // 1. Try popping from the local queue first (the fastest path)
         if let Some(job) = self.inner.pop() {
             log::trace!(
                 "Worker {} popped from local queue: {}",
                 self.id,
-                job.path.display()
+                escape_path(&job.path)
             );
             return Some(job);
         }
 
         // 2. Try stealing from the global queue with an adaptive batch size
         if let Some(job) = self.steal_from_global() {
+            self.stats.global_steals += 1;
             return Some(job);
         }
 
         // 3. Try stealing from other workers
-        self.steal_from_victims()
+        let job = self.steal_from_victims();
+        if job.is_some() {
+            self.stats.victim_steals += 1;
+        }
+        job
     }
 
-    /// Steal from the global queue with adaptive batching
+    /// Steal from the global queue with adaptive batching.
+    ///
+    /// A worker takes its fair share of the global queue (queue length
+    /// divided by the number of workers), at least one job and at most the
+    /// configured batch size. A small batch size spreads work more evenly,
+    /// a large one means fewer trips to the shared queue.
     fn steal_from_global(&self) -> Option<Job> {
-        // Calculate a fair batch size based on queue length
-        let batch_size = (self.injector.len() / self.num_workers)
-            .max(1)   // Always try to steal at least 1
-            .min(32); // Cap at 32 to avoid hogging
+        let num_workers = self.stealers.len();
+        let batch_size = (self.injector.len() / num_workers).clamp(1, self.batch_size);
 
         loop {
             match self.injector.steal_batch_with_limit_and_pop(&self.inner, batch_size) {
@@ -152,7 +317,7 @@ impl WalkWorker {
         // Try each worker's queue in sequence
         for (i, stealer) in self.stealers.iter().enumerate() {
             // Skip stealing from self
-            if i == self.id {
+            if i == self.slot {
                 continue;
             }
 
@@ -176,16 +341,67 @@ impl WalkWorker {
         None
     }
 
+    /// Publish the jobs produced by a finished job and retire the job itself.
+    ///
+    /// Jobs go to the local queue, where this worker picks them up first and
+    /// idle workers can still steal them, up to `local_queue_cap`. Past that
+    /// they are pushed to the global queue so a huge directory does not pile
+    /// up in a single worker.
+    ///
+    /// The global counter holds the number of jobs that are queued or being
+    /// processed. New jobs are added to it *before* they become visible to
+    /// other workers, and the finished job is removed in the same operation,
+    /// so the counter can only reach zero once every job has been processed.
+    ///
+    /// In a device pool, jobs on another device are routed to the pool of
+    /// their device first, and counted there.
+    ///
+    /// Parked workers are woken when there are new jobs to steal, or when
+    /// the counter reaches zero so they can terminate.
+    fn publish_jobs(&mut self, global_job_counter: &AtomicI64) {
+        if let Some(router) = &self.router {
+            let device = router.device();
+            let foreign = |job: &mut Job| job.dev.is_some_and(|dev| dev != device);
+            for job in self.processor.new_jobs.extract_if(.., foreign) {
+                router.route(job);
+            }
+        }
+        let produced = self.processor.new_jobs.len() as i64;
+        let remaining = global_job_counter.fetch_add(produced - 1, Ordering::AcqRel) + produced - 1;
+        let cap = self.config.performance_config.local_queue_cap;
+        for job in self.processor.new_jobs.drain(..) {
+            if self.inner.len() < cap {
+                self.inner.push(job);
+            } else {
+                self.injector.push(job);
+                self.stats.jobs_redistributed += 1;
+            }
+        }
+        if produced > 0 || remaining == 0 {
+            self.parking.unpark_all();
+        }
+    }
+
+    /// Whether a parked worker has something to do: jobs to steal, or a
+    /// walk to leave
+    fn has_work(&self, global_job_counter: &AtomicI64, cancelled: &AtomicBool) -> bool {
+        self.should_terminate(global_job_counter)
+            || cancelled.load(Ordering::Relaxed)
+            || !self.injector.is_empty()
+            || self.stealers.iter().any(|stealer| !stealer.is_empty())
+    }
+
     /// Check if this worker should terminate
     #[inline]
-    fn should_terminate(&self, global_job_counter: &Arc<AtomicI64>) -> bool {
+    fn should_terminate(&self, global_job_counter: &AtomicI64) -> bool {
         global_job_counter.load(Ordering::Acquire) == 0
-            && self.inner.is_empty()
-            && self.injector.is_empty()
-            && self.stealers.iter().all(|s| s.len() == 0)
     }
 
-    pub fn run_loop(&mut self, global_job_counter: Arc<AtomicI64>) -> anyhow::Result<WorkerResult> {
+    pub fn run_loop(
+        &mut self,
+        global_job_counter: Arc<AtomicI64>,
+        cancelled: &AtomicBool,
+    ) -> anyhow::Result<WorkerResult> {
         // Setup fastrace span for this function
         #[cfg(debug_assertions)]
         let (_worker_span, _guard) = {
@@ -198,146 +414,59 @@ impl WalkWorker {
         let mut idle_cycles = 0;
 
         loop {
+            // Queued jobs are abandoned, the counter no longer matters
+            if cancelled.load(Ordering::Relaxed) {
+                log::info!("Worker {} cancelled", self.id);
+                break;
+            }
+
             // Try to find work using the three-tier strategy
             match self.find_work() {
                 Some(job) => {
                     idle_cycles = 0; // Reset idle counter
 
-                    if let Err(_) = self.process_job(&job) {
-                        self.errors_count += 1;
-                    }
+                    self.processor.process(&job);
+                    self.stats.jobs_processed += 1;
+                    self.publish_jobs(&global_job_counter);
                 }
                 None => {
-                    // No work found, enter an exponential backoff sequence
-                    idle_cycles += 1;
-                    match idle_cycles {
-                        // Phase 1: Light spinning (1-9 cycles)
-                        1..=9 => {
-                            std::hint::spin_loop();
-                        }
-                        // Phase 2: Yielding to scheduler (10-999 cycles)
-                        10..=999 => {
-                            std::thread::yield_now();
-                        }
-                        // Phase 3: Sync local work delta (at cycle 1000)
-                        1000 => {
-                            if self.local_work_delta != 0 {
-                                global_job_counter.fetch_add(
-                                    self.local_work_delta,
-                                    Ordering::AcqRel
-                                );
-                                self.local_work_delta = 0;
-                            }
-                            std::thread::yield_now();
-                        }
-                        // Phase 4: Keep waiting (1001-4999 cycles)
-                        1001..=4999 => {
-                            std::thread::yield_now();
-                        }
-                        // Phase 5: Final termination check (5000+ cycles)
-                        _ => {
-                            if self.should_terminate(&global_job_counter) {
-                                log::info!(
-                                    "Worker {} terminating: dirs={}, files={}, errors={}",
-                                    self.id,
-                                    self.dirs_processed,
-                                    self.files_processed,
-                                    self.errors_count
-                                );
-                                break;
-                            }
-                            // Reset to stay in the synced phase
-                            idle_cycles = 1001;
-                            std::thread::yield_now();
-                        }
+                    // No job is queued or in flight anywhere, or in the
+                    // worker's pool
+                    if self.should_terminate(&global_job_counter)
+                        && self.router.as_ref().is_none_or(|router| router.retire())
+                    {
+                        log::info!(
+                            "Worker {} terminating: dirs={}, files={}, errors={}, redistributed={}",
+                            self.id,
+                            self.processor.dirs_processed,
+                            self.processor.files_processed,
+                            self.processor.error_counts.total(),
+                            self.stats.jobs_redistributed
+                        );
+                        break;
                     }
-                }
-            }
-        }
-        anyhow::Ok(WorkerResult::new(&self))
-    }
 
-    fn process_job(&mut self, job: &Job) -> anyhow::Result<(), anyhow::Error> {
-        // Check max depth
-        if let Some(max) = self.max_depth {
-            if job.depth > max {
-                return Err(anyhow::anyhow!("Worker {} has reached max depth: {} > {}", self.id, job.depth, max));
-            }
-        }
-
-        // Consume a job from the queue
-        self.local_work_delta -= 1;
-
-        // Short path if the root path is a file
-        if !job.is_dir {
-            self.files_processed += 1;
-             return self.process_file(&job);
-        }
-
-        // Read entries
-        match fs::read_dir(&job.path) {
-            Ok(entries) => {
-                for entry in entries {
-                    match entry {
-                        Ok(entry) => {
-                            if let Some(ft) = entry.file_type().ok() {
-                                let parent = entry.path().parent().map(|p| p.to_path_buf());
-                                let mut new_job =
-                                    Job::new(entry.path(), parent, job.depth + 1, false);
-                                if ft.is_dir() {
-                                    // Send to global queue or batch and then send
-                                    new_job.is_dir = true;
-                                    self.injector.push(new_job);
-                                    self.local_work_delta += 1;
-                                } else {
-                                    self.files_processed += 1;
-                                    if let Err(_) = self.process_file(&new_job) {
-                                        self.errors_count += 1;
-                                    }
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            self.errors_count += 1;
-                            log::error!("Worker {} failed to read directory entry, skipping: {}", self.id, err);
-                        }
+                    // No work found, back off while other workers are busy
+                    idle_cycles += 1;
+                    self.stats.idle_cycles += 1;
+                    if idle_cycles <= self.idle.spin_cycles {
+                        // Light spinning
+                        std::hint::spin_loop();
+                    } else if idle_cycles <= self.idle.yield_cycles {
+                        // Yield to scheduler
+                        std::thread::yield_now();
+                    } else {
+                        // Nothing for a while, stop competing for a core
+                        self.stats.parks += 1;
+                        self.parking.park(self.idle.park_timeout, || {
+                            self.has_work(&global_job_counter, cancelled)
+                        });
                     }
                 }
-                self.dirs_processed += 1;
-                anyhow::Ok(())
-            }
-            Err(err) => {
-                log::error!("Worker {} failed to open directory {}: {}", self.id, job.path.display(), err);
-                Err(err.into())
-            }
-        }
-    }
-
-    fn process_file(&mut self, job: &Job) -> Result<(), anyhow::Error>{
-        match job.path.symlink_metadata() {
-            Ok(metadata) => {
-                if !is_special_file(&metadata.file_type()) {
-                    self.total_blocks += metadata.blocks();
-                }
-                anyhow::Ok(())
-            }
-            Err(err) => {
-                log::error!(
-                    "Worker {} failed to read metadata for file: {}, error: {}",
-                    self.id,
-                    job.path.display(),
-                    err
-                );
-                Err(err.into())
             }
         }
+        let mut result = self.processor.take_result();
+        result.scheduler = Some(self.stats);
+        anyhow::Ok(result)
     }
 }
-
-fn is_special_file(file_type: &fs::FileType) -> bool {
-    file_type.is_block_device()
-        || file_type.is_char_device()
-        || file_type.is_fifo()
-        || file_type.is_socket()
-        || file_type.is_symlink()
-}
@@ -0,0 +1,84 @@
+use crate::utils::escape_path;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Errors produced while walking, so library users can match on the kind of failure.
+///
+/// Per-entry errors (`PermissionDenied`, `Io`) are recoverable: the entry is
+/// skipped and the walk goes on, unless the `ErrorPolicy` of the
+/// configuration stops it. `InvalidPattern` is fatal and reported
+/// before walking starts.
+#[derive(Debug, thiserror::Error)]
+pub enum WalkError {
+    #[error("permission denied: {}", escape_path(.0))]
+    PermissionDenied(PathBuf),
+
+    #[error("{}: {}", escape_path(.0), .1)]
+    Io(PathBuf, #[source] io::Error),
+
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(String),
+}
+
+impl WalkError {
+    /// Classify an I/O error that happened on `path`
+    pub fn io(path: &Path, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => WalkError::PermissionDenied(path.to_path_buf()),
+            _ => WalkError::Io(path.to_path_buf(), err),
+        }
+    }
+
+    /// Path of the entry that failed, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            WalkError::PermissionDenied(path) | WalkError::Io(path, _) => Some(path),
+            WalkError::InvalidPattern(_) => None,
+        }
+    }
+
+    /// Stable name of the kind of failure, for machine-readable reports
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WalkError::PermissionDenied(_) => "permission_denied",
+            WalkError::Io(..) => "io",
+            WalkError::InvalidPattern(_) => "invalid_pattern",
+        }
+    }
+
+    /// What went wrong, without the path
+    pub fn message(&self) -> String {
+        match self {
+            WalkError::PermissionDenied(_) => "permission denied".to_string(),
+            WalkError::Io(_, err) => err.to_string(),
+            WalkError::InvalidPattern(pattern) => pattern.clone(),
+        }
+    }
+}
+
+/// Number of per-entry errors by kind
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorCounts {
+    pub permission_denied: usize,
+    pub io: usize,
+}
+
+impl ErrorCounts {
+    pub fn record(&mut self, err: &WalkError) {
+        match err {
+            WalkError::PermissionDenied(_) => self.permission_denied += 1,
+            _ => self.io += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &ErrorCounts) {
+        self.permission_denied += other.permission_denied;
+        self.io += other.io;
+    }
+
+    pub fn total(&self) -> usize {
+        self.permission_denied + self.io
+    }
+}
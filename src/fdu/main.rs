@@ -1,6 +1,9 @@
-use anyhow::Result;
-use clap::Parser;
-use fdu::{cli, core::walker};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use fdu::{ScanResult, config::Config, config_file, error::WalkError, output, snapshot, watch::Watch};
 use logforth::{
     append,
     colored::Colorize,
@@ -8,10 +11,13 @@ use logforth::{
 };
 
 #[derive(Debug)]
-pub struct CustomTextLayout {}
+pub struct CustomTextLayout {
+    /// Include the module that logged, the target FDU_LOG directives match
+    show_target: bool,
+}
 impl CustomTextLayout {
-    fn new() -> Self {
-        CustomTextLayout {}
+    fn new(show_target: bool) -> Self {
+        CustomTextLayout { show_target }
     }
 }
 
@@ -37,29 +43,194 @@ impl logforth::layout::Layout for CustomTextLayout {
         //     log::Level::Trace => "TRACE",
         // };
 
-        // let origin_source_file = record.target();
-
-        let formatted = format!("[{}] {}", level_str, record.args());
+        let formatted = if self.show_target {
+            format!("[{} {}] {}", level_str, record.target(), record.args())
+        } else {
+            format!("[{}] {}", level_str, record.args())
+        };
         Ok(formatted.into_bytes())
     }
 }
 
-fn main() -> Result<()> {
-    let cli = cli::Cli::parse();
-    let filter_builder = EnvFilterBuilder::try_from_env("FDU_LOG").unwrap_or_else(|| {
-        let default_level = if cfg!(debug_assertions) {
-            log::LevelFilter::Debug
+/// Scan, writing every displayed entry as a JSON line as soon as it is
+/// final: files while walking, directories once the walk is done
+fn stream_entries(out: &mut (impl Write + Send + Sync), config: &Config) -> Result<ScanResult> {
+    let mut write_error = None;
+    let paths = output::DisplayPaths::new(&config.paths, config.output_config.path_style);
+    let result = fdu::scan_with(config, |entry| {
+        if write_error.is_none() && output::is_displayed(entry, &config.output_config) {
+            write_error = output::json::write_entry_line(out, entry, &paths, &config.output_config).err();
+        }
+    })?;
+    if let Some(err) = write_error {
+        return Err(err.into());
+    }
+    Ok(result)
+}
+
+/// Where the results are written: the --output-file, or stdout
+fn open_output(config: &Config) -> Result<BufWriter<Box<dyn Write + Send + Sync>>> {
+    let out: Box<dyn Write + Send + Sync> = match &config.output_config.output_file {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("Failed to create output file {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+    Ok(BufWriter::new(out))
+}
+
+/// Scan and write the results to `out`
+fn scan_and_write(out: &mut (impl Write + Send + Sync), config: &Config) -> Result<ScanResult> {
+    let result = if config.output_config.streams_entries() {
+        stream_entries(out, config)?
+    } else if let Some(limit) = config.output_config.exceeds {
+        let result = fdu::scan(config)?;
+        output::write_exceeds(out, &result, limit, &config.output_config)?;
+        result
+    } else {
+        let mut result = fdu::scan(config)?;
+        // The whole scan is saved, before anything is filtered out for display
+        if let Some(path) = &config.output_config.save {
+            snapshot::save(path, &result, config.output_config.time_field)?;
+        }
+        output::filter_entries(&mut result.entries, &config.output_config);
+        if config.output_config.top.is_some() {
+            output::top_entries(&mut result.entries, &config.output_config);
         } else {
-            log::LevelFilter::Info
-        };
-        EnvFilterBuilder::new().filter_level(default_level)
-    });
+            output::sort_entries(&mut result.entries, &config.output_config);
+        }
+
+        output::write_report(out, &result, &config.output_config)?;
+        result
+    };
+    out.flush()?;
+
+    if let Some(path) = &config.output_config.error_json {
+        write_error_report(path, &result.buffered_errors)?;
+    }
+    Ok(result)
+}
+
+/// Report on stderr what the caller should know about a scan besides its
+/// results, and the exit status it calls for
+fn finish_scan(result: &ScanResult, config: &Config) -> Result<ExitCode> {
+    fastrace::flush();
+
+    if config.output_config.stats {
+        output::write_stats(&mut io::stderr().lock(), result)?;
+    }
+
+    if result.timed_out {
+        writeln!(
+            io::stderr().lock(),
+            "fdu: warning: scan stopped after the --max-time limit, totals are partial"
+        )?;
+    }
+    if let Some(estimate) = &result.estimate {
+        writeln!(
+            io::stderr().lock(),
+            "fdu: warning: sizes are {}, not exact",
+            output::estimate_note(estimate, result.total_bytes)
+        )?;
+    }
+    if result.aborted {
+        writeln!(
+            io::stderr().lock(),
+            "fdu: warning: scan stopped on the first error (--on-error abort), totals are partial"
+        )?;
+    }
+    if let Some(dir) = &result.max_entries_reached {
+        writeln!(
+            io::stderr().lock(),
+            "fdu: error: scan stopped after more than {} entries (--max-entries), {} took it over, totals are partial",
+            config.performance_config.max_entries.unwrap_or_default(),
+            fdu::utils::escape_path(dir)
+        )?;
+    }
+    if result.panics > 0 {
+        writeln!(
+            io::stderr().lock(),
+            "fdu: error: {} internal errors interrupted the scan, totals are partial",
+            result.panics
+        )?;
+    }
+
+    // Like du, a scan that skipped entries is a failure, unless the
+    // caller expects partial scans
+    if result.errors.total() > 0 {
+        output::write_error_summary(
+            &mut io::stderr().lock(),
+            &result.errors,
+            &result.buffered_errors,
+        )?;
+        if !config.traverse_config.ignore_errors {
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    // Like a failed check, the limit being exceeded is the answer
+    if result.timed_out || result.panics > 0 || result.exceeded.is_some() || result.max_entries_reached.is_some() {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Scan again every time files change under the paths, until interrupted.
+/// Each scan replaces the output file, or follows the previous one on
+/// stdout, the screen being cleared first when it is a terminal.
+fn watch(watch: &Watch, config: &Config) -> Result<ExitCode> {
+    let clear_screen = config.output_config.output_file.is_none()
+        && !config.output_config.streams_entries()
+        && io::stdout().is_terminal();
+    loop {
+        let changed = watch.wait()?;
+        log::info!("{} paths changed, scanning again", changed.len());
+        // Every path is scanned again, `changed` holds the subtrees an
+        // incremental scan would limit itself to
+        for path in &changed {
+            log::debug!("Changed subtree: {}", path.display());
+        }
+        let mut out = open_output(config)?;
+        if clear_screen {
+            write!(out, "\x1b[2J\x1b[H")?;
+        }
+        let result = scan_and_write(&mut out, config)?;
+        finish_scan(&result, config)?;
+    }
+}
+
+/// Write the entries skipped on error to `path` as JSON Lines, an empty
+/// file when there were none
+fn write_error_report(path: &Path, errors: &[WalkError]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create error report {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    output::json::write_errors(&mut out, errors)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = config_file::parse_args(std::env::args_os())?;
+    // Logs go to stderr, color them only if it is a terminal
+    logforth::colored::control::set_override(cli.color.enabled(io::stderr().is_terminal()));
+    // Quiet mode only lets errors through, whatever FDU_LOG asks for
+    let filter_builder = if cli.quiet {
+        EnvFilterBuilder::new().filter_level(log::LevelFilter::Error)
+    } else {
+        EnvFilterBuilder::try_from_env("FDU_LOG").unwrap_or_else(|| {
+            let default_level = if cfg!(debug_assertions) {
+                log::LevelFilter::Debug
+            } else {
+                log::LevelFilter::Info
+            };
+            EnvFilterBuilder::new().filter_level(default_level)
+        })
+    };
 
     logforth::builder()
         .dispatch(|d| {
             let dispatch = d
                 .filter(EnvFilter::new(filter_builder))
-                .append(append::Stderr::default().with_layout(CustomTextLayout::new()));
+                .append(append::Stderr::default().with_layout(CustomTextLayout::new(cli.log_target)));
             // if cli.trace {
             //     dispatch = dispatch.append(append::FastraceEvent::default());
             // }
@@ -67,9 +238,45 @@ fn main() -> Result<()> {
         })
         .apply();
 
-    log::info!("Starting fdu v{}, threads: {}", env!("CARGO_PKG_VERSION"), cli.threads);
-    let multi_walker = walker::Multithreaded::new(cli.threads);
-    multi_walker.walk(cli.paths[0].clone())?;
-    fastrace::flush();
-    Ok(())
+    let config = Config::from_cli(&cli)?;
+
+    if cli.dry_run {
+        let mut out = BufWriter::new(io::stdout().lock());
+        output::write_plan(&mut out, &cli.paths, &config)?;
+        out.flush()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut out = open_output(&config)?;
+
+    // Comparing two saved scans does not scan anything
+    if let Some((old, new)) = &config.output_config.diff {
+        let deltas = snapshot::diff(
+            &snapshot::load(old)?,
+            &snapshot::load(new)?,
+            config.output_config.apparent_size,
+        )?;
+        output::write_diff(&mut out, &deltas, &config.output_config)?;
+        out.flush()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    log::info!(
+        "Starting fdu v{}, threads: {}",
+        env!("CARGO_PKG_VERSION"),
+        config.performance_config.threads
+    );
+    // Watching starts before the first scan, so that no change made
+    // while it runs is missed
+    let watcher = if config.output_config.watch {
+        Some(Watch::new(&config)?)
+    } else {
+        None
+    };
+    let result = scan_and_write(&mut out, &config)?;
+    let status = finish_scan(&result, &config)?;
+    match &watcher {
+        Some(watcher) => watch(watcher, &config),
+        None => Ok(status),
+    }
 }
@@ -1,4 +1,13 @@
 pub mod cli;
 pub mod config;
+pub mod config_file;
 pub mod core;
+pub mod error;
+pub mod output;
+pub mod scan;
+pub mod snapshot;
 pub mod utils;
+pub mod watch;
+
+pub use crate::core::aggregate::Entry;
+pub use scan::{ScanResult, scan, scan_with};
@@ -0,0 +1,43 @@
+use crate::config::OutputConfig;
+use crate::core::aggregate::Entry;
+
+/// Drop entries that should not be displayed.
+///
+/// Runs after aggregation, so hidden entries still count towards the
+/// totals of their parents.
+pub fn filter_entries(entries: &mut Vec<Entry>, config: &OutputConfig) {
+    // Tree exports need the whole tree
+    if config.exports_tree() {
+        return;
+    }
+    entries.retain(|entry| is_displayed(entry, config));
+}
+
+/// Whether an entry should be displayed, also for entries streamed one by
+/// one as the walk produces them
+pub fn is_displayed(entry: &Entry, config: &OutputConfig) -> bool {
+    // Only the roots (one per input path) carry the grand subtree total
+    if config.summarize && entry.depth != 0 {
+        return false;
+    }
+    // Root files are always displayed, others only with --all/--files-only
+    if !entry.is_dir && entry.depth != 0 && !config.shows_files() {
+        return false;
+    }
+    if !config.within_depth(entry.depth) {
+        return false;
+    }
+    // A single level, each entry carrying its whole subtree
+    if config.depth_summary.is_some_and(|depth| entry.depth != depth) {
+        return false;
+    }
+    if (config.dirs_only && !entry.is_dir) || (config.files_only && entry.is_dir) {
+        return false;
+    }
+    if entry.is_dir && config.min_files.is_some_and(|min| entry.files < min) {
+        return false;
+    }
+    config
+        .threshold
+        .is_none_or(|threshold| config.size_of(entry) >= threshold)
+}
@@ -0,0 +1,235 @@
+use crate::cli::TimeField;
+use crate::config::OutputConfig;
+use crate::core::aggregate::{Entry, blocks_to_bytes};
+use crate::core::duplicates::DuplicateSet;
+use crate::core::extensions::ExtensionBreakdown;
+use crate::core::histogram::Histogram;
+use crate::core::mounts::MountBreakdown;
+use crate::error::WalkError;
+use crate::output::DisplayPaths;
+use crate::snapshot::DirDelta;
+use crate::utils::escape_path;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// JSON view of an entry. Paths are escaped with `escape_path` so names
+/// that are not valid UTF-8 survive the round trip.
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    path: Cow<'a, str>,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    size: u64,
+    files: u64,
+    /// Files and directories, only with --inodes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inodes: Option<u64>,
+    /// The time selected with --time, keyed by its name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    atime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ctime: Option<i64>,
+}
+
+impl<'a> JsonEntry<'a> {
+    fn new(entry: &'a Entry, paths: &'a DisplayPaths, config: &OutputConfig) -> Self {
+        Self {
+            path: match paths.get(entry) {
+                Cow::Borrowed(path) => escape_path(path),
+                Cow::Owned(path) => Cow::Owned(escape_path(&path).into_owned()),
+            },
+            kind: if entry.is_dir { "dir" } else { "file" },
+            size: entry.size(config.apparent_size),
+            files: entry.files,
+            inodes: config.inodes.then(|| entry.inodes()),
+            mtime: (config.time_field == TimeField::Mtime).then_some(entry.time),
+            atime: (config.time_field == TimeField::Atime).then_some(entry.time),
+            ctime: (config.time_field == TimeField::Ctime).then_some(entry.time),
+        }
+    }
+}
+
+/// Write all entries as a single JSON array, sizes in bytes
+pub fn write_entries(
+    out: &mut impl Write,
+    entries: &[Entry],
+    paths: &DisplayPaths,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let entries: Vec<JsonEntry> = entries
+        .iter()
+        .map(|entry| JsonEntry::new(entry, paths, config))
+        .collect();
+    serde_json::to_writer(&mut *out, &entries)?;
+    writeln!(out)
+}
+
+/// Write an entry as a JSON object on a line of its own, for NDJSON
+pub fn write_entry_line(
+    out: &mut impl Write,
+    entry: &Entry,
+    paths: &DisplayPaths,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, &JsonEntry::new(entry, paths, config))?;
+    writeln!(out)
+}
+
+/// JSON view of a histogram bucket, `max` is null for the last bucket
+#[derive(Serialize)]
+struct JsonBucket {
+    min: u64,
+    max: Option<u64>,
+    files: u64,
+    size: u64,
+}
+
+/// Write the file size histogram as a JSON array of buckets, sizes in bytes
+pub fn write_histogram(
+    out: &mut impl Write,
+    histogram: &Histogram,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let buckets: Vec<JsonBucket> = histogram
+        .buckets
+        .iter()
+        .map(|bucket| JsonBucket {
+            min: bucket.min,
+            max: bucket.max,
+            files: bucket.files,
+            size: bucket.size(config.apparent_size),
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &buckets)?;
+    writeln!(out)
+}
+
+/// JSON view of the totals of an extension
+#[derive(Serialize)]
+struct JsonExtension {
+    files: u64,
+    size: u64,
+}
+
+/// Write the totals per file extension as a JSON object keyed by extension,
+/// or per content type keyed by type
+pub fn write_extensions(
+    out: &mut impl Write,
+    extensions: &ExtensionBreakdown,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let extensions: BTreeMap<&str, JsonExtension> = extensions
+        .totals
+        .iter()
+        .map(|(extension, totals)| {
+            let totals = JsonExtension {
+                files: totals.files,
+                size: totals.size(config.apparent_size),
+            };
+            (extension.as_str(), totals)
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &extensions)?;
+    writeln!(out)
+}
+
+/// Write the totals per filesystem as a JSON object keyed by mount point
+pub fn write_mounts(out: &mut impl Write, mounts: &MountBreakdown, config: &OutputConfig) -> io::Result<()> {
+    let mounts: BTreeMap<String, JsonExtension> = mounts
+        .sorted(config.apparent_size)
+        .into_iter()
+        .map(|(mount_point, totals)| {
+            let totals = JsonExtension {
+                files: totals.files,
+                size: totals.size(config.apparent_size),
+            };
+            (mount_point, totals)
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &mounts)?;
+    writeln!(out)
+}
+
+/// Write paths as a JSON array of strings
+pub fn write_paths(out: &mut impl Write, paths: &[PathBuf]) -> io::Result<()> {
+    let paths: Vec<Cow<str>> = paths.iter().map(|path| escape_path(path)).collect();
+    serde_json::to_writer(&mut *out, &paths)?;
+    writeln!(out)
+}
+
+/// Write the size changes between two snapshots as a JSON array, sizes in bytes
+pub fn write_diff(out: &mut impl Write, deltas: &[DirDelta]) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, deltas)?;
+    writeln!(out)
+}
+
+/// JSON view of a sparse file
+#[derive(Serialize)]
+struct JsonSparseFile<'a> {
+    path: Cow<'a, str>,
+    apparent_size: u64,
+    allocated_size: u64,
+}
+
+/// Write sparse files as a JSON array, sizes in bytes
+pub fn write_sparse_files(out: &mut impl Write, files: &[Entry]) -> io::Result<()> {
+    let files: Vec<JsonSparseFile> = files
+        .iter()
+        .map(|file| JsonSparseFile {
+            path: escape_path(&file.path),
+            apparent_size: file.apparent_size,
+            allocated_size: blocks_to_bytes(file.blocks),
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &files)?;
+    writeln!(out)
+}
+
+/// JSON view of a set of duplicate files
+#[derive(Serialize)]
+struct JsonDuplicateSet<'a> {
+    size: u64,
+    reclaimable: u64,
+    paths: Vec<Cow<'a, str>>,
+}
+
+/// Write sets of duplicate files as a JSON array, sizes in bytes
+pub fn write_duplicates(out: &mut impl Write, sets: &[DuplicateSet]) -> io::Result<()> {
+    let sets: Vec<JsonDuplicateSet> = sets
+        .iter()
+        .map(|set| JsonDuplicateSet {
+            size: set.size,
+            reclaimable: set.reclaimable(),
+            paths: set.paths.iter().map(|path| escape_path(path)).collect(),
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &sets)?;
+    writeln!(out)
+}
+
+/// JSON view of an entry skipped on error
+#[derive(Serialize)]
+struct JsonError<'a> {
+    path: Option<Cow<'a, str>>,
+    kind: &'static str,
+    message: String,
+}
+
+/// Write errors as JSON Lines, one object per error
+pub fn write_errors(out: &mut impl Write, errors: &[WalkError]) -> io::Result<()> {
+    for err in errors {
+        let err = JsonError {
+            path: err.path().map(escape_path),
+            kind: err.kind(),
+            message: err.message(),
+        };
+        serde_json::to_writer(&mut *out, &err)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
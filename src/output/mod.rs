@@ -0,0 +1,676 @@
+use crate::cli::{OutputFormat, PercentOf, SizeFormat};
+use crate::config::OutputConfig;
+use crate::core::aggregate::{BLOCK_UNIT, Entry, blocks_to_bytes};
+use crate::core::duplicates::DuplicateSet;
+use crate::core::extensions::ExtensionBreakdown;
+use crate::core::histogram::Histogram;
+use crate::core::mounts::MountBreakdown;
+use crate::core::sample::Estimate;
+use crate::core::worker;
+use crate::error::{ErrorCounts, WalkError};
+use crate::scan::ScanResult;
+use crate::snapshot::DirDelta;
+use jiff::{Timestamp, tz::TimeZone};
+use logforth::colored::Color;
+use num_format::ToFormattedString;
+use unicode_width::UnicodeWidthStr;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub mod filter;
+pub mod json;
+pub mod ncdu;
+pub mod path_style;
+pub mod plan;
+pub mod prometheus;
+pub mod sort;
+pub mod sqlite;
+pub mod tree;
+pub mod treemap;
+
+pub use filter::{filter_entries, is_displayed};
+pub use path_style::DisplayPaths;
+pub use plan::write_plan;
+pub use sort::{sort_entries, top_entries};
+
+/// Format a size in bytes according to the output configuration.
+///
+/// With a block size set the size is reported as a number of blocks,
+/// rounded up like `du -B`. Otherwise the size format decides, the fixed
+/// units are rounded up the same way.
+pub fn format_size(bytes: u64, config: &OutputConfig) -> String {
+    let units = |unit: u64| format_number(bytes.div_ceil(unit), config);
+    if let Some(block_size) = config.block_size {
+        return units(block_size);
+    }
+    match config.size_format {
+        SizeFormat::Human | SizeFormat::Si => humansize::format_size(bytes, humansize::DECIMAL),
+        SizeFormat::Binary => humansize::format_size(bytes, humansize::BINARY),
+        SizeFormat::Bytes => units(1),
+        SizeFormat::Blocks => units(BLOCK_UNIT),
+        SizeFormat::Hex => format!("{bytes:#x}"),
+        SizeFormat::Kilo => units(1 << 10),
+        SizeFormat::Mega => units(1 << 20),
+        SizeFormat::Giga => units(1 << 30),
+    }
+}
+
+/// Format a plain number, with digit grouping if --thousands is set
+fn format_number(value: u64, config: &OutputConfig) -> String {
+    match &config.thousands {
+        Some(format) => value.to_formatted_string(format),
+        None => value.to_string(),
+    }
+}
+
+/// Format a unix timestamp (seconds) in the local timezone with a
+/// strftime format, like `du --time`
+pub fn format_time(secs: i64, tz: &TimeZone, format: &str) -> String {
+    Timestamp::from_second(secs)
+        .ok()
+        .and_then(|ts| jiff::fmt::strtime::format(format, &ts.to_zoned(tz.clone())).ok())
+        .unwrap_or_else(|| secs.to_string())
+}
+
+/// Write the scan result in the configured output format
+pub fn write_report(
+    out: &mut impl Write,
+    result: &ScanResult,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    if let Some(histogram) = &result.histogram {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_histogram(out, histogram, config),
+            _ => write_histogram(out, histogram, config),
+        };
+    }
+    if let Some(extensions) = &result.extensions {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_extensions(out, extensions, config),
+            _ => write_extensions(out, extensions, config),
+        };
+    }
+    if let Some(types) = &result.types {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_extensions(out, types, config),
+            _ => write_types(out, types, config),
+        };
+    }
+
+    if let Some(mounts) = &result.mounts {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_mounts(out, mounts, config),
+            _ => write_mounts(out, mounts, config),
+        };
+    }
+    if let Some(empty) = &result.empty {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_paths(out, empty),
+            _ => write_paths(out, empty, config),
+        };
+    }
+    if let Some(sets) = &result.duplicates {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_duplicates(out, sets),
+            _ => write_duplicates(out, sets, config),
+        };
+    }
+    if let Some(sparse_files) = &result.sparse_files {
+        return match config.output_format {
+            Some(OutputFormat::Json) => json::write_sparse_files(out, sparse_files),
+            _ => write_sparse_files(out, sparse_files, config),
+        };
+    }
+
+    match config.output_format {
+        Some(OutputFormat::Json) => {
+            let paths = DisplayPaths::new(&result.roots, config.path_style);
+            json::write_entries(out, &result.entries, &paths, config)
+        }
+        // Already written while scanning
+        Some(OutputFormat::Ndjson) => Ok(()),
+        Some(OutputFormat::Ncdu) => ncdu::write_tree(out, &result.entries, &result.buffered_errors),
+        Some(OutputFormat::Treemap) => {
+            let total = if config.inodes { result.total_inodes } else { result.total_bytes };
+            treemap::write_tree(out, &result.entries, total, config)
+        }
+        Some(OutputFormat::Prometheus) => prometheus::write_metrics(out, &result.entries, config),
+        Some(OutputFormat::Sqlite) => match &config.db {
+            Some(db) => sqlite::write_entries(db, &result.entries, config),
+            None => Ok(()),
+        },
+        Some(OutputFormat::Raw) => write_raw(out, result, config),
+        Some(OutputFormat::Porcelain) => write_porcelain(out, result, config),
+        None => {
+            let grand_total = if config.inodes { result.total_inodes } else { result.total_bytes };
+            let percents = config.percent.map(|of| percent_column(result, grand_total, of, config));
+            let total = config.total.then_some((grand_total, result.total_files));
+            let paths = DisplayPaths::new(&result.roots, config.path_style);
+            write_entries(out, &result.entries, &paths, percents.as_deref(), total, config)?;
+            if !config.total_line {
+                return Ok(());
+            }
+            if config.inodes {
+                write!(out, "Total inodes: {}", format_number(result.total_inodes, config))?;
+            } else if let Some(estimate) = &result.estimate {
+                write!(
+                    out,
+                    "Total size: ~{} ({})",
+                    format_size(result.total_bytes, config),
+                    estimate_note(estimate, result.total_bytes)
+                )?;
+            } else {
+                write!(out, "Total size: {}", format_size(result.total_bytes, config))?;
+            }
+            end_record(out, config)
+        }
+    }
+}
+
+/// Write a path as its raw bytes, so names that are not valid UTF-8 are
+/// printed exactly as they are on disk
+fn write_path(out: &mut impl Write, path: &Path) -> io::Result<()> {
+    out.write_all(path.as_os_str().as_encoded_bytes())
+}
+
+/// Terminate an output record with newline, or NUL with --null
+fn end_record(out: &mut impl Write, config: &OutputConfig) -> io::Result<()> {
+    let separator = if config.null_terminated { b'\0' } else { b'\n' };
+    out.write_all(&[separator])
+}
+
+/// Write `text` right-aligned in a column `width` terminal columns wide
+fn write_right_aligned(out: &mut impl Write, text: &str, width: usize) -> io::Result<()> {
+    let padding = width.saturating_sub(text.width());
+    write!(out, "{:padding$}{text}", "")
+}
+
+/// Color of a size on a green, yellow, red gradient, red for the
+/// largest entry
+fn heat_color(value: u64, largest: u64) -> Color {
+    let ratio = if largest == 0 { 0.0 } else { value as f64 / largest as f64 };
+    // Red rises over the first half, green falls over the second
+    let red = (ratio * 2.0).min(1.0);
+    let green = ((1.0 - ratio) * 2.0).min(1.0);
+    Color::TrueColor {
+        r: (red * 220.0) as u8,
+        g: (green * 200.0) as u8,
+        b: 0,
+    }
+}
+
+/// How a --sample estimate was made and how far off `total` may be, like
+/// `estimated from a 10% sample, ±1.2% at 95% confidence`
+pub fn estimate_note(estimate: &Estimate, total: u64) -> String {
+    let percent = |fraction: f64| {
+        let percent = format!("{:.2}", fraction * 100.0);
+        format!("{}%", percent.trim_end_matches('0').trim_end_matches('.'))
+    };
+    let margin = estimate.margin as f64 / total.max(1) as f64;
+    format!(
+        "estimated from a {} sample, ±{} at 95% confidence",
+        percent(estimate.rate),
+        percent(margin)
+    )
+}
+
+/// Format what an entry is displayed by: a number of inodes with
+/// --inodes, otherwise a size
+fn format_measure(value: u64, config: &OutputConfig) -> String {
+    if config.inodes {
+        format_number(value, config)
+    } else {
+        format_size(value, config)
+    }
+}
+
+/// Share of each entry in its parent directory, or in the grand total,
+/// for the --percent column. Roots are always shares of the grand total.
+fn percent_column(result: &ScanResult, grand_total: u64, of: PercentOf, config: &OutputConfig) -> Vec<String> {
+    result
+        .entries
+        .iter()
+        .map(|entry| {
+            let base = match of {
+                PercentOf::Parent if entry.depth > 0 => entry
+                    .path
+                    .parent()
+                    .and_then(|parent| result.dir_sizes.get(parent))
+                    .copied()
+                    .unwrap_or(grand_total),
+                _ => grand_total,
+            };
+            format_percent(config.size_of(entry), base)
+        })
+        .collect()
+}
+
+/// `part` as a percentage of `whole`, with one decimal
+fn format_percent(part: u64, whole: u64) -> String {
+    if whole == 0 {
+        return "0.0%".to_string();
+    }
+    format!("{:.1}%", part as f64 * 100.0 / whole as f64)
+}
+
+/// Write one line per entry: size, optional percent, file count and time
+/// columns, then path as displayed by `paths`. With a grand total, given as
+/// its size and files, its line follows the entries, like `du -c`.
+///
+/// Sizes, percents and counts are right-aligned in columns as wide as the
+/// widest of them, so a long listing can be scanned down the column like
+/// `du -h`.
+pub fn write_entries(
+    out: &mut impl Write,
+    entries: &[Entry],
+    paths: &DisplayPaths,
+    percents: Option<&[String]>,
+    total: Option<(u64, u64)>,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let sizes: Vec<String> = entries
+        .iter()
+        .map(|entry| format_measure(config.size_of(entry), config))
+        .collect();
+    let counts: Option<Vec<String>> = config
+        .count
+        .then(|| entries.iter().map(|entry| format_number(entry.files, config)).collect());
+    let total_count = total.filter(|_| config.count).map(|(_, files)| format_number(files, config));
+    let count_width = counts
+        .iter()
+        .flatten()
+        .chain(&total_count)
+        .map(|count| count.width())
+        .max()
+        .unwrap_or(0);
+    let total = total.map(|(value, _)| format_measure(value, config));
+    let width = sizes.iter().chain(&total).map(|size| size.width()).max().unwrap_or(0);
+    let total_percent = String::from("100.0%");
+    let percent_width = percents
+        .unwrap_or_default()
+        .iter()
+        .chain(total.as_ref().map(|_| &total_percent))
+        .map(|percent| percent.width())
+        .max()
+        .unwrap_or(0);
+
+    let largest = entries.iter().map(|entry| config.size_of(entry)).max().unwrap_or(0);
+    let tz = TimeZone::system();
+    for (i, (entry, size)) in entries.iter().zip(&sizes).enumerate() {
+        if config.color_by_size {
+            let color = heat_color(config.size_of(entry), largest);
+            write!(out, "\x1b[{}m", color.to_fg_str())?;
+            write_right_aligned(out, size, width)?;
+            out.write_all(b"\x1b[0m")?;
+        } else {
+            write_right_aligned(out, size, width)?;
+        }
+        out.write_all(b"\t")?;
+        if let Some(percents) = percents {
+            write_right_aligned(out, &percents[i], percent_width)?;
+            out.write_all(b"\t")?;
+        }
+        if let Some(counts) = &counts {
+            write_right_aligned(out, &counts[i], count_width)?;
+            out.write_all(b"\t")?;
+        }
+        if config.show_time {
+            write!(out, "{}\t", format_time(entry.time, &tz, &config.time_format))?;
+        }
+        write_path(out, &paths.get(entry))?;
+        end_record(out, config)?;
+    }
+    if let Some(total) = total {
+        write_right_aligned(out, &total, width)?;
+        out.write_all(b"\t")?;
+        if percents.is_some() {
+            write_right_aligned(out, &total_percent, percent_width)?;
+            out.write_all(b"\t")?;
+        }
+        if let Some(total_count) = &total_count {
+            write_right_aligned(out, total_count, count_width)?;
+            out.write_all(b"\t")?;
+        }
+        out.write_all(b"total")?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Write one `size<TAB>files<TAB>path` line per entry, the size in bytes
+/// (inodes with --inodes), followed by a `total` line with --total.
+///
+/// Meant for scripts: nothing is formatted and nothing is escaped, so a
+/// path containing a tab, or a newline without --null, is an error.
+pub fn write_raw(out: &mut impl Write, result: &ScanResult, config: &OutputConfig) -> io::Result<()> {
+    let paths = DisplayPaths::new(&result.roots, config.path_style);
+    for entry in &result.entries {
+        let path = paths.get(entry);
+        let bytes = path.as_os_str().as_encoded_bytes();
+        if bytes.contains(&b'\t') || (!config.null_terminated && bytes.contains(&b'\n')) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Path {path:?} has a tab or newline, it cannot be written in the raw output"),
+            ));
+        }
+        write!(out, "{}\t{}\t", config.size_of(entry), entry.files)?;
+        write_path(out, &path)?;
+        end_record(out, config)?;
+    }
+    if config.total {
+        let total = if config.inodes { result.total_inodes } else { result.total_bytes };
+        write!(out, "{total}\t{}\ttotal", result.total_files)?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Write the porcelain output: one `size<TAB>path` line per entry, the
+/// size in bytes, sorted by path whatever the sort options.
+///
+/// This format is a promise to scripts and must never change: no header,
+/// no total, no color and no locale formatting. Only the line terminator
+/// follows --null, a path containing a newline without it is an error.
+pub fn write_porcelain(out: &mut impl Write, result: &ScanResult, config: &OutputConfig) -> io::Result<()> {
+    let paths = DisplayPaths::new(&result.roots, config.path_style);
+    let mut entries: Vec<(Cow<Path>, &Entry)> = result.entries.iter().map(|entry| (paths.get(entry), entry)).collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, entry) in entries {
+        if !config.null_terminated && path.as_os_str().as_encoded_bytes().contains(&b'\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Path {path:?} has a newline, it cannot be written in the porcelain output without --null"),
+            ));
+        }
+        write!(out, "{}\t", entry.size(config.apparent_size))?;
+        write_path(out, &path)?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Write the size changes between two snapshots, one line per directory:
+/// signed change, new size, then path
+pub fn write_diff(out: &mut impl Write, deltas: &[DirDelta], config: &OutputConfig) -> io::Result<()> {
+    if matches!(config.output_format, Some(OutputFormat::Json)) {
+        return json::write_diff(out, deltas);
+    }
+    for delta in deltas {
+        let sign = if delta.new_size >= delta.old_size { '+' } else { '-' };
+        let change = format_size(delta.new_size.abs_diff(delta.old_size), config);
+        let size = format_size(delta.new_size, config);
+        write!(out, "{sign}{change}\t{size}\t{}", delta.path)?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Short label for a bucket bound, in binary units (4K, 64K, 1M, ...)
+fn bound_label(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024 && value.is_multiple_of(1024) && unit < UNITS.len() - 1 {
+        value /= 1024;
+        unit += 1;
+    }
+    format!("{value}{}", UNITS[unit])
+}
+
+/// Write the file size histogram as an aligned table, one row per bucket
+pub fn write_histogram(
+    out: &mut impl Write,
+    histogram: &Histogram,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let mut rows: Vec<[String; 3]> = vec![["RANGE".into(), "FILES".into(), "SIZE".into()]];
+    for bucket in &histogram.buckets {
+        let range = match bucket.max {
+            Some(max) => format!("{}-{}", bound_label(bucket.min), bound_label(max)),
+            None => format!("{}+", bound_label(bucket.min)),
+        };
+        rows.push([
+            range,
+            bucket.files.to_string(),
+            format_size(bucket.size(config.apparent_size), config),
+        ]);
+    }
+    write_table(out, &rows, config)
+}
+
+/// Write the totals per file extension as an aligned table, largest first
+pub fn write_extensions(
+    out: &mut impl Write,
+    extensions: &ExtensionBreakdown,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    write_breakdown(out, "EXTENSION", extensions, config)
+}
+
+/// Write the totals per content type as an aligned table, largest first
+pub fn write_types(out: &mut impl Write, types: &ExtensionBreakdown, config: &OutputConfig) -> io::Result<()> {
+    write_breakdown(out, "TYPE", types, config)
+}
+
+/// Write totals under a key column headed `key`, largest first
+fn write_breakdown(
+    out: &mut impl Write,
+    key: &str,
+    breakdown: &ExtensionBreakdown,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let mut rows: Vec<[String; 3]> = vec![[key.into(), "FILES".into(), "SIZE".into()]];
+    for (key, totals) in breakdown.sorted(config.apparent_size) {
+        rows.push([
+            key.to_string(),
+            totals.files.to_string(),
+            format_size(totals.size(config.apparent_size), config),
+        ]);
+    }
+    write_table(out, &rows, config)
+}
+
+/// Write the totals per filesystem as an aligned table, largest first
+pub fn write_mounts(out: &mut impl Write, mounts: &MountBreakdown, config: &OutputConfig) -> io::Result<()> {
+    let mut rows: Vec<[String; 3]> = vec![["MOUNT".into(), "FILES".into(), "SIZE".into()]];
+    for (mount_point, totals) in mounts.sorted(config.apparent_size) {
+        rows.push([
+            mount_point,
+            totals.files.to_string(),
+            format_size(totals.size(config.apparent_size), config),
+        ]);
+    }
+    write_table(out, &rows, config)
+}
+
+/// Share of the apparent size of a file that is not allocated, in percent
+fn unallocated_percent(entry: &Entry) -> f64 {
+    entry.unallocated() as f64 * 100.0 / entry.apparent_size.max(1) as f64
+}
+
+/// Write sparse files as a table of apparent size, allocated size and the
+/// share of the apparent size saved, then path
+pub fn write_sparse_files(out: &mut impl Write, files: &[Entry], config: &OutputConfig) -> io::Result<()> {
+    let mut rows: Vec<[String; 3]> = vec![["APPARENT".into(), "ALLOCATED".into(), "SAVED".into()]];
+    for file in files {
+        rows.push([
+            format_size(file.apparent_size, config),
+            format_size(blocks_to_bytes(file.blocks), config),
+            format!("{:.1}%", unallocated_percent(file)),
+        ]);
+    }
+    let widths = column_widths(&rows);
+    let paths = std::iter::once(None).chain(files.iter().map(|file| Some(&file.path)));
+    for (row, path) in rows.iter().zip(paths) {
+        for (cell, width) in row.iter().zip(widths) {
+            write_right_aligned(out, cell, width)?;
+            out.write_all(b"  ")?;
+        }
+        match path {
+            Some(path) => write_path(out, path)?,
+            None => out.write_all(b"PATH")?,
+        }
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Write paths as they are, one per line
+pub fn write_paths(out: &mut impl Write, paths: &[PathBuf], config: &OutputConfig) -> io::Result<()> {
+    for path in paths {
+        write_path(out, path)?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Write whether the paths go over the --exceeds `limit`: the path that
+/// did and the entry that took it over, or else each path with its size
+pub fn write_exceeds(out: &mut impl Write, result: &ScanResult, limit: u64, config: &OutputConfig) -> io::Result<()> {
+    if let Some(exceeded) = &result.exceeded {
+        write_path(out, &result.roots[exceeded.root])?;
+        write!(out, ": over {}, passed at ", format_size(limit, config))?;
+        write_path(out, &exceeded.tripped_by)?;
+        return end_record(out, config);
+    }
+    let mut roots: Vec<&Entry> = result.entries.iter().filter(|entry| entry.depth == 0).collect();
+    roots.sort_by_key(|entry| entry.root);
+    for root in roots {
+        write_path(out, &root.path)?;
+        write!(
+            out,
+            ": {}, within {}",
+            format_size(config.size_of(root), config),
+            format_size(limit, config)
+        )?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Write each set of duplicate files as a line with the size of each
+/// copy and the space reclaimable, then its paths, sets separated by an
+/// empty line, then the total reclaimable space
+pub fn write_duplicates(out: &mut impl Write, sets: &[DuplicateSet], config: &OutputConfig) -> io::Result<()> {
+    for set in sets {
+        write!(
+            out,
+            "{} x {}, {} reclaimable",
+            set.paths.len(),
+            format_size(set.size, config),
+            format_size(set.reclaimable(), config)
+        )?;
+        end_record(out, config)?;
+        write_paths(out, &set.paths, config)?;
+        end_record(out, config)?;
+    }
+    let reclaimable = sets.iter().map(DuplicateSet::reclaimable).sum();
+    write!(out, "Reclaimable: {} in {} sets", format_size(reclaimable, config), sets.len())?;
+    end_record(out, config)
+}
+
+/// Width of each column: the width of its widest cell
+fn column_widths<const N: usize>(rows: &[[String; N]]) -> [usize; N] {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    widths
+}
+
+/// Write rows of a label column followed by two right-aligned columns
+fn write_table(out: &mut impl Write, rows: &[[String; 3]], config: &OutputConfig) -> io::Result<()> {
+    let widths = column_widths(rows);
+    for [label, files, size] in rows {
+        let padding = widths[0].saturating_sub(label.width());
+        write!(out, "{label}{:padding$}  ", "")?;
+        write_right_aligned(out, files, widths[1])?;
+        out.write_all(b"  ")?;
+        write_right_aligned(out, size, widths[2])?;
+        end_record(out, config)?;
+    }
+    Ok(())
+}
+
+/// Format a count with a metric suffix, like 350k or 1.2M
+fn format_count(count: f64) -> String {
+    const SUFFIXES: [(f64, &str); 3] = [(1e9, "G"), (1e6, "M"), (1e3, "k")];
+    for (scale, suffix) in SUFFIXES {
+        if count >= scale {
+            let scaled = count / scale;
+            return if scaled < 10.0 {
+                format!("{scaled:.1}{suffix}")
+            } else {
+                format!("{scaled:.0}{suffix}")
+            };
+        }
+    }
+    format!("{count:.0}")
+}
+
+/// Write the scan duration and throughput, like
+/// `scanned 1.2M files in 3.4s (350k files/s)`
+pub fn write_stats(out: &mut impl Write, result: &ScanResult) -> io::Result<()> {
+    let secs = result.duration.as_secs_f64();
+    // Avoid dividing by zero on instant scans
+    let rate = |count: usize| count as f64 / secs.max(f64::EPSILON);
+    writeln!(
+        out,
+        "fdu: scanned {} files and {} directories in {:.1}s ({} files/s, {} dirs/s), {} errors",
+        format_count(result.files_scanned as f64),
+        format_count(result.dirs_scanned as f64),
+        secs,
+        format_count(rate(result.files_scanned)),
+        format_count(rate(result.dirs_scanned)),
+        result.errors.total()
+    )?;
+    for (id, stats) in result.workers.iter().enumerate() {
+        writeln!(
+            out,
+            "fdu: worker {id}: {} jobs, {} global steals, {} victim steals, {} idle cycles, {} parks, {} redistributed",
+            stats.jobs_processed,
+            stats.global_steals,
+            stats.victim_steals,
+            stats.idle_cycles,
+            stats.parks,
+            stats.jobs_redistributed
+        )?;
+    }
+    if let Some((min, max, mean)) = worker::jobs_spread(&result.workers) {
+        writeln!(out, "fdu: jobs per worker: min {min}, max {max}, mean {mean:.1}")?;
+    }
+    Ok(())
+}
+
+/// Number of example paths shown per kind of error in the summary
+const ERROR_EXAMPLES: usize = 3;
+
+/// Write the error breakdown, followed by a few example paths per kind
+/// when errors were buffered
+pub fn write_error_summary(
+    out: &mut impl Write,
+    counts: &ErrorCounts,
+    errors: &[WalkError],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "fdu: scan incomplete: {} permission denied, {} I/O errors",
+        counts.permission_denied, counts.io
+    )?;
+
+    let (denied, other): (Vec<&WalkError>, Vec<&WalkError>) = errors
+        .iter()
+        .partition(|err| matches!(err, WalkError::PermissionDenied(_)));
+    for group in [&denied, &other] {
+        for err in group.iter().take(ERROR_EXAMPLES) {
+            writeln!(out, "  {err}")?;
+        }
+        if group.len() > ERROR_EXAMPLES {
+            writeln!(out, "  ... and {} more", group.len() - ERROR_EXAMPLES)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::core::aggregate::{Entry, blocks_to_bytes};
+use crate::error::WalkError;
+use crate::output::tree::{self, Tree};
+use crate::utils::escape_path;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Version of the ncdu export format written, as `[major, minor, ...]`
+const FORMAT_VERSION: (u32, u32) = (1, 2);
+
+/// Header object following the format version
+#[derive(Serialize)]
+struct Metadata {
+    progname: &'static str,
+    progver: &'static str,
+    timestamp: i64,
+}
+
+/// Information of a single file or directory. For a directory the sizes
+/// only cover the directory itself, ncdu adds up the subtree on import.
+#[derive(Serialize)]
+struct Info<'a> {
+    name: Cow<'a, str>,
+    asize: u64,
+    dsize: u64,
+    /// Set on directories that could not be read, or only in part
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read_error: Option<bool>,
+    /// Set on files with several hard links, which ncdu counts once per
+    /// inode number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ino: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hlnkc: Option<bool>,
+}
+
+/// Write the tree of the (single) input path in the ncdu JSON export
+/// format, so it can be browsed with `ncdu -f`.
+///
+/// Needs every directory and file entry of the walk, before filtering,
+/// and the errors of the walk to flag the directories that failed.
+pub fn write_tree(out: &mut impl Write, entries: &[Entry], errors: &[WalkError]) -> io::Result<()> {
+    let tree = Tree::new(entries);
+    let failed: HashSet<&Path> = errors.iter().filter_map(WalkError::path).collect();
+    let metadata = Metadata {
+        progname: env!("CARGO_PKG_NAME"),
+        progver: env!("CARGO_PKG_VERSION"),
+        timestamp: jiff::Timestamp::now().as_second(),
+    };
+    write!(out, "[{},{},", FORMAT_VERSION.0, FORMAT_VERSION.1)?;
+    serde_json::to_writer(&mut *out, &metadata)?;
+    if let Some(root) = tree.roots.first() {
+        out.write_all(b",")?;
+        write_entry(out, root, &tree, &failed)?;
+    }
+    writeln!(out, "]")
+}
+
+/// Write a file as an object, or a directory as an array of its own
+/// information followed by its children
+fn write_entry(out: &mut impl Write, entry: &Entry, tree: &Tree, failed: &HashSet<&Path>) -> io::Result<()> {
+    let children = tree.children(entry);
+
+    // Entries carry subtree totals, what the children do not account
+    // for belongs to the directory itself
+    let mut info = Info {
+        name: escape_path(tree::name(entry)),
+        asize: entry.apparent_size,
+        dsize: blocks_to_bytes(entry.blocks),
+        read_error: failed.contains(entry.path.as_path()).then_some(true),
+        ino: entry.ino,
+        hlnkc: entry.ino.map(|_| true),
+    };
+    for child in children {
+        info.asize = info.asize.saturating_sub(child.apparent_size);
+        info.dsize = info.dsize.saturating_sub(blocks_to_bytes(child.blocks));
+    }
+
+    if !entry.is_dir {
+        return serde_json::to_writer(&mut *out, &info).map_err(io::Error::from);
+    }
+    out.write_all(b"[")?;
+    serde_json::to_writer(&mut *out, &info)?;
+    for child in children {
+        out.write_all(b",")?;
+        write_entry(out, child, tree, failed)?;
+    }
+    out.write_all(b"]")
+}
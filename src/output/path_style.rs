@@ -0,0 +1,57 @@
+use crate::cli::PathStyle;
+use crate::core::aggregate::Entry;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// Paths of the entries as displayed with --path-style.
+///
+/// Only the input paths are canonicalized, once each and only for
+/// absolute paths. Entries below them are joined onto the result, the
+/// walk does not follow symlinks so no entry needs a syscall of its own.
+pub struct DisplayPaths {
+    style: PathStyle,
+    /// Canonical input paths, indexed by `Entry::root`, only for absolute
+    /// paths
+    roots: Vec<PathBuf>,
+}
+
+impl DisplayPaths {
+    pub fn new(roots: &[PathBuf], style: PathStyle) -> Self {
+        let roots = match style {
+            PathStyle::Absolute => roots
+                .iter()
+                .map(|root| {
+                    std::fs::canonicalize(root)
+                        .or_else(|_| std::path::absolute(root))
+                        .unwrap_or_else(|_| root.clone())
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Self { style, roots }
+    }
+
+    /// The path of an entry, as displayed
+    pub fn get<'p>(&'p self, entry: &'p Entry) -> Cow<'p, Path> {
+        match self.style {
+            PathStyle::Given => Cow::Borrowed(&entry.path),
+            PathStyle::Name => Cow::Borrowed(entry.path.file_name().map_or(&entry.path, Path::new)),
+            PathStyle::Relative => match below_root(entry) {
+                below if below.as_os_str().is_empty() => Cow::Borrowed(Path::new(".")),
+                below => Cow::Borrowed(below),
+            },
+            PathStyle::Absolute => match self.roots.get(entry.root) {
+                Some(root) if entry.depth == 0 => Cow::Borrowed(root),
+                Some(root) => Cow::Owned(root.join(below_root(entry))),
+                None => Cow::Borrowed(&entry.path),
+            },
+        }
+    }
+}
+
+/// The part of an entry's path below the input path it was found under
+fn below_root(entry: &Entry) -> &Path {
+    // An entry at depth N is its input path followed by N components
+    let root = entry.path.ancestors().nth(entry.depth).unwrap_or(&entry.path);
+    entry.path.strip_prefix(root).unwrap_or(&entry.path)
+}
@@ -0,0 +1,147 @@
+use crate::config::Config;
+use crate::core::handle;
+use crate::core::mounts::{self, device_label};
+use crate::utils::escape_path;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Write what a scan would do, without walking: the roots and the
+/// filesystems they span, then the resolved settings.
+///
+/// `requested` are the paths given on the command line, those missing
+/// from the configuration are listed as skipped.
+pub fn write_plan(out: &mut impl Write, requested: &[PathBuf], config: &Config) -> io::Result<()> {
+    let mount_points = mounts::mount_points();
+
+    writeln!(out, "roots:")?;
+    for path in &config.paths {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let filesystem = match handle::lstat(path) {
+            Ok(stat) => device_label(stat.dev, &mount_points),
+            Err(err) => format!("unknown ({err})"),
+        };
+        writeln!(out, "  {} (filesystem {})", escape_path(path), filesystem)?;
+
+        // Mount points inside the root, where the walk changes filesystem
+        let mut boundaries: Vec<&PathBuf> = mount_points
+            .values()
+            .filter(|mount_point| mount_point.starts_with(&canonical) && **mount_point != canonical)
+            .collect();
+        boundaries.sort();
+        boundaries.dedup();
+        for mount_point in boundaries {
+            writeln!(out, "    crosses into {}", escape_path(mount_point))?;
+        }
+    }
+    let skipped = requested.iter().filter(|path| !config.paths.contains(path));
+    for path in skipped {
+        let reason = if path.exists() {
+            "counted in another path"
+        } else {
+            "does not exist"
+        };
+        writeln!(out, "skipped: {} ({reason})", escape_path(path))?;
+    }
+
+    let performance = &config.performance_config;
+    match performance.threads_per_device {
+        Some(threads) => writeln!(out, "threads: {threads} per device")?,
+        None => writeln!(out, "threads: {}", performance.threads)?,
+    }
+    if let Some(max_time) = performance.max_time {
+        writeln!(out, "max time: {max_time:?}")?;
+    }
+    if let Some(max_entries) = performance.max_entries {
+        writeln!(out, "max entries: {max_entries}")?;
+    }
+    if let Some(sample) = performance.sample {
+        writeln!(out, "sample rate: {}", sample.rate())?;
+    }
+
+    let traverse = &config.traverse_config;
+    // Accepted on the command line, but the walk does not act on them yet
+    if traverse.follow_symlinks {
+        writeln!(out, "follow symlinks: not implemented")?;
+    }
+    if !traverse.cross_filesystems {
+        writeln!(out, "one file system: not implemented")?;
+    }
+    if let Some(min_depth) = traverse.min_depth {
+        writeln!(out, "min depth: {min_depth}, not implemented")?;
+    }
+    writeln!(out, "count hard links: {}", traverse.count_hard_links)?;
+    writeln!(out, "count special files: {}", traverse.include_special)?;
+    writeln!(out, "on error: {:?}", traverse.on_error)?;
+    if traverse.ignore_errors {
+        writeln!(out, "ignore errors: true")?;
+    }
+    if let Some(prune_depth) = traverse.prune_depth {
+        writeln!(out, "prune depth: {prune_depth}")?;
+    }
+
+    let filter = &config.filter_config;
+    for pattern in &filter.exclude_patterns {
+        writeln!(out, "exclude regex: {pattern}")?;
+    }
+    for pattern in &filter.include_patterns {
+        writeln!(out, "include regex: {pattern}")?;
+    }
+    if !filter.exclude_globs.is_empty() {
+        writeln!(out, "exclude globs: {}", filter.exclude_globs.len())?;
+    }
+    if !filter.include_globs.is_empty() {
+        writeln!(out, "include globs: {}", filter.include_globs.len())?;
+    }
+    if filter.gitignore {
+        writeln!(out, "gitignore: true")?;
+    }
+    if filter.min_size.is_some() || filter.max_size.is_some() {
+        writeln!(out, "file size: {:?} to {:?} bytes", filter.min_size, filter.max_size)?;
+    }
+    if filter.newer_than.is_some() || filter.older_than.is_some() {
+        writeln!(out, "modified: {:?} to {:?} (seconds since epoch)", filter.newer_than, filter.older_than)?;
+    }
+    if filter.uid.is_some() || filter.gid.is_some() {
+        writeln!(out, "owner: uid {:?}, gid {:?}", filter.uid, filter.gid)?;
+    }
+
+    let output = &config.output_config;
+    match output.output_format {
+        Some(format) => writeln!(out, "output: {format:?}")?,
+        None => writeln!(out, "output: text")?,
+    }
+    writeln!(out, "size format: {:?}", output.size_format)?;
+    writeln!(out, "apparent size: {}", output.apparent_size)?;
+    writeln!(out, "path style: {:?}", output.path_style)?;
+    if output.count {
+        writeln!(out, "file count column: true")?;
+    }
+    if let Some(max_depth) = output.max_depth {
+        writeln!(out, "max depth: {max_depth}")?;
+    }
+    if let Some(depth) = output.depth_summary {
+        writeln!(out, "depth summary: {depth}")?;
+    }
+    if let Some(field) = output.sort_field {
+        writeln!(out, "sort: {field:?}{}", if output.reverse { ", reversed" } else { "" })?;
+    }
+    if let Some(top) = output.top {
+        writeln!(out, "top: {top}")?;
+    }
+    if let Some(min) = output.duplicates {
+        writeln!(out, "duplicates: files of at least {min} bytes")?;
+    }
+    if let Some(bytes) = output.by_type {
+        writeln!(out, "by type: first {bytes} bytes of each file read")?;
+    }
+    if let Some(limit) = output.exceeds {
+        writeln!(out, "exceeds: stop once a path goes over {limit} bytes")?;
+    }
+    if let Some(threshold) = output.threshold {
+        writeln!(out, "threshold: {threshold} bytes")?;
+    }
+    if let Some(min_files) = output.min_files {
+        writeln!(out, "min files: {min_files}")?;
+    }
+    Ok(())
+}
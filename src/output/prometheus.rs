@@ -0,0 +1,49 @@
+use crate::config::OutputConfig;
+use crate::core::aggregate::Entry;
+use crate::utils::escape_path;
+use std::io::{self, Write};
+
+/// Escape a label value for the Prometheus text exposition format:
+/// backslash, double quote and newline are backslash-escaped
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write one gauge sample per displayed directory, in the Prometheus text
+/// format read by node_exporter's textfile collector.
+///
+/// Samples of a metric have to be grouped together, so every directory
+/// appears once under each metric.
+pub fn write_metrics(
+    out: &mut impl Write,
+    entries: &[Entry],
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let dirs: Vec<(String, &Entry)> = entries
+        .iter()
+        .filter(|entry| entry.is_dir)
+        .map(|entry| (escape_label(&escape_path(&entry.path)), entry))
+        .collect();
+
+    writeln!(out, "# HELP fdu_directory_bytes Size of the directory and its contents in bytes.")?;
+    writeln!(out, "# TYPE fdu_directory_bytes gauge")?;
+    for (path, entry) in &dirs {
+        writeln!(out, "fdu_directory_bytes{{path=\"{path}\"}} {}", entry.size(config.apparent_size))?;
+    }
+
+    writeln!(out, "# HELP fdu_directory_files Number of files in the directory and its subdirectories.")?;
+    writeln!(out, "# TYPE fdu_directory_files gauge")?;
+    for (path, entry) in &dirs {
+        writeln!(out, "fdu_directory_files{{path=\"{path}\"}} {}", entry.files)?;
+    }
+    Ok(())
+}
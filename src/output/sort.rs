@@ -0,0 +1,74 @@
+use crate::cli::SortField;
+use crate::config::OutputConfig;
+use crate::core::aggregate::Entry;
+use crate::core::retention::BySize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Compare two entries by a single field, in ascending order
+fn compare(a: &Entry, b: &Entry, field: SortField, config: &OutputConfig) -> Ordering {
+    match field {
+        SortField::Name => a.path.cmp(&b.path),
+        SortField::Size => config.size_of(a).cmp(&config.size_of(b)),
+        SortField::Count => a.files.cmp(&b.files),
+        SortField::Time => a.time.cmp(&b.time),
+    }
+}
+
+/// Sort entries by the configured field, or by name when none is given.
+///
+/// Ties are broken by path so the order is deterministic regardless of
+/// how the workers produced the entries. `reverse` only flips the
+/// primary field, ties stay in path order. Without a field the entries of
+/// each path given stay together, in the order the paths were given, and
+/// `reverse` flips the name order within each of them.
+pub fn sort_entries(entries: &mut [Entry], config: &OutputConfig) {
+    let Some(field) = config.sort_field else {
+        entries.sort_by(|a, b| {
+            let ordering = a.path.cmp(&b.path);
+            let ordering = if config.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            a.root.cmp(&b.root).then(ordering)
+        });
+        return;
+    };
+
+    entries.sort_by(|a, b| {
+        let ordering = compare(a, b, field, config);
+        let ordering = if config.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        ordering.then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// Keep only the configured number of largest entries, largest first.
+///
+/// A min-heap bounded to N entries is used, so selecting a few entries out
+/// of a large tree does not sort the whole set.
+pub fn top_entries(entries: &mut Vec<Entry>, config: &OutputConfig) {
+    let Some(n) = config.top else {
+        return;
+    };
+
+    let mut heap = BinaryHeap::with_capacity(n.saturating_add(1).min(entries.len()));
+    for entry in entries.drain(..) {
+        heap.push(Reverse(BySize {
+            size: config.size_of(&entry),
+            entry,
+        }));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    entries.extend(
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.entry),
+    );
+}
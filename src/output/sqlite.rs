@@ -0,0 +1,40 @@
+use crate::config::OutputConfig;
+use crate::core::aggregate::Entry;
+use crate::utils::escape_path;
+use rusqlite::{Connection, params};
+use std::io;
+use std::path::Path;
+
+/// Write the entries to the `entries` table of a SQLite database, replacing
+/// the table if it already exists. Sizes are in bytes.
+///
+/// All rows are inserted in a single transaction through one prepared
+/// statement, which keeps millions of rows fast to load.
+pub fn write_entries(db: &Path, entries: &[Entry], config: &OutputConfig) -> io::Result<()> {
+    write_database(db, entries, config).map_err(io::Error::other)
+}
+
+fn write_database(db: &Path, entries: &[Entry], config: &OutputConfig) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(db)?;
+    let transaction = connection.transaction()?;
+    // The time column is named after the timestamp selected with --time
+    transaction.execute_batch(&format!(
+        "DROP TABLE IF EXISTS entries;
+         CREATE TABLE entries (path TEXT, size INTEGER, files INTEGER, {} INTEGER, depth INTEGER);",
+        config.time_field.name()
+    ))?;
+    {
+        let mut insert = transaction.prepare("INSERT INTO entries VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for entry in entries {
+            // SQLite integers are signed, sizes past i64::MAX are clamped
+            insert.execute(params![
+                escape_path(&entry.path),
+                i64::try_from(entry.size(config.apparent_size)).unwrap_or(i64::MAX),
+                i64::try_from(entry.files).unwrap_or(i64::MAX),
+                entry.time,
+                i64::try_from(entry.depth).unwrap_or(i64::MAX),
+            ])?;
+        }
+    }
+    transaction.commit()
+}
@@ -0,0 +1,40 @@
+use crate::core::aggregate::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The aggregated entries arranged back into the directory tree they came
+/// from, for the exports that nest entries
+pub struct Tree<'a> {
+    /// The entries of the input paths, in input order
+    pub roots: Vec<&'a Entry>,
+    children: HashMap<&'a Path, Vec<&'a Entry>>,
+}
+
+impl<'a> Tree<'a> {
+    pub fn new(entries: &'a [Entry]) -> Self {
+        let mut roots = Vec::new();
+        let mut children: HashMap<&Path, Vec<&Entry>> = HashMap::new();
+        for entry in entries {
+            if entry.depth == 0 {
+                roots.push(entry);
+            } else if let Some(parent) = entry.path.parent() {
+                children.entry(parent).or_default().push(entry);
+            }
+        }
+        roots.sort_by_key(|entry| entry.root);
+        Self { roots, children }
+    }
+
+    /// The entries directly under a directory
+    pub fn children(&self, entry: &Entry) -> &[&'a Entry] {
+        self.children.get(entry.path.as_path()).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Name of an entry within its parent, roots keep the path they were given as
+pub fn name(entry: &Entry) -> &Path {
+    match entry.path.file_name() {
+        Some(name) if entry.depth > 0 => Path::new(name),
+        _ => &entry.path,
+    }
+}
@@ -0,0 +1,54 @@
+use crate::config::OutputConfig;
+use crate::core::aggregate::Entry;
+use crate::output::tree::{self, Tree};
+use crate::utils::escape_path;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// A node in the `{ name, value, children }` shape of D3's hierarchy
+/// layouts (treemap, sunburst...). Files are leaves without children.
+#[derive(Serialize)]
+struct Node<'a> {
+    name: Cow<'a, str>,
+    value: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<Node<'a>>>,
+}
+
+impl<'a> Node<'a> {
+    fn new(entry: &'a Entry, tree: &Tree<'a>, config: &OutputConfig) -> Self {
+        Self {
+            name: escape_path(tree::name(entry)),
+            value: config.size_of(entry),
+            // Directories at --max-depth are leaves carrying their total
+            children: (entry.is_dir && config.within_depth(entry.depth + 1)).then(|| {
+                tree.children(entry)
+                    .iter()
+                    .map(|child| Node::new(child, tree, config))
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Write the scanned tree as nested JSON nodes for web visualizations.
+///
+/// Every node carries the total of its subtree as `value`. Several input
+/// paths are gathered under a `total` node. Levels past --max-depth are
+/// left out.
+pub fn write_tree(out: &mut impl Write, entries: &[Entry], total: u64, config: &OutputConfig) -> io::Result<()> {
+    let tree = Tree::new(entries);
+    let mut roots: Vec<Node> = tree.roots.iter().map(|root| Node::new(root, &tree, config)).collect();
+    let node = if roots.len() == 1 {
+        roots.remove(0)
+    } else {
+        Node {
+            name: Cow::Borrowed("total"),
+            value: total,
+            children: Some(roots),
+        }
+    };
+    serde_json::to_writer(&mut *out, &node)?;
+    writeln!(out)
+}
@@ -0,0 +1,235 @@
+use crate::cli::{ErrorPolicy, PercentOf};
+use crate::config::Config;
+use crate::core::aggregate::Entry;
+use crate::core::duplicates::DuplicateSet;
+use crate::core::extensions::ExtensionBreakdown;
+use crate::core::histogram::Histogram;
+use crate::core::mounts::MountBreakdown;
+use crate::core::limits::Exceeded;
+use crate::core::sample::Estimate;
+use crate::core::walker::{Multithreaded, SingleThreaded, Visitor, WalkResult, Walker};
+use crate::core::worker::SchedulerStats;
+use crate::error::{ErrorCounts, WalkError};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Structured result of a scan, nothing is printed while scanning
+#[derive(Debug)]
+pub struct ScanResult {
+    /// Aggregated directories (and files when retained), unfiltered and unsorted
+    pub entries: Vec<Entry>,
+    /// The input paths scanned, indexed by `Entry::root`
+    pub roots: Vec<PathBuf>,
+    /// Grand total across all paths, apparent size or disk usage as configured
+    pub total_bytes: u64,
+    /// Number of files counted in the grand total
+    pub total_files: u64,
+    /// Number of inodes, files and directories, in the grand total
+    pub total_inodes: u64,
+    /// Number of directories and files visited
+    pub dirs_scanned: usize,
+    pub files_scanned: usize,
+    /// Wall-clock time of the walk and the aggregation
+    pub duration: Duration,
+    /// Number of entries that could not be read, by kind
+    pub errors: ErrorCounts,
+    /// The errors themselves, only kept with --buffer-errors
+    pub buffered_errors: Vec<WalkError>,
+    /// File size histogram, only with --histogram
+    pub histogram: Option<Histogram>,
+    /// Totals per file extension, only with --by-extension
+    pub extensions: Option<ExtensionBreakdown>,
+    /// Totals per content type, only with --by-type
+    pub types: Option<ExtensionBreakdown>,
+    /// Sparse files, the largest gap between apparent and allocated size
+    /// first, only with --sparse
+    pub sparse_files: Option<Vec<Entry>>,
+    /// Totals per filesystem, only with --by-mount
+    pub mounts: Option<MountBreakdown>,
+    /// Empty directories and zero-byte files in path order, only with --empty
+    pub empty: Option<Vec<PathBuf>>,
+    /// Sets of files with identical content, the most reclaimable space
+    /// first, only with --duplicates
+    pub duplicates: Option<Vec<DuplicateSet>>,
+    /// Directories, symlinks and special files left out of the totals,
+    /// only with --regular-only
+    pub non_regular: u64,
+    /// How far off the totals may be, only with --sample where every
+    /// size is an estimate
+    pub estimate: Option<Estimate>,
+    /// Size of every directory before filtering, as displayed, only with
+    /// --percent parent
+    pub dir_sizes: HashMap<PathBuf, u64>,
+    /// Scheduling of each worker, empty when walking on a single thread
+    pub workers: Vec<SchedulerStats>,
+    /// Whether the walk was stopped by --max-time, totals are then partial
+    pub timed_out: bool,
+    /// Whether the walk was stopped by an error with --on-error abort,
+    /// totals are then partial
+    pub aborted: bool,
+    /// Directories left half-scanned by an internal error (a panic), and
+    /// threads lost to one with everything they counted. Totals are then
+    /// partial whatever the error policy.
+    pub panics: usize,
+    /// The input path found to go over the limit, with --exceeds. The
+    /// walk stops there, totals are then partial.
+    pub exceeded: Option<Exceeded>,
+    /// The directory that took the walk past --max-entries, where it was
+    /// stopped. Totals are then partial.
+    pub max_entries_reached: Option<PathBuf>,
+}
+
+/// Walk every path of the configuration and aggregate the results
+pub fn scan(config: &Config) -> anyhow::Result<ScanResult> {
+    let start = Instant::now();
+    let result = walk(config, None)?;
+    let duration = start.elapsed();
+    let roots = result.entries.iter().filter(|entry| entry.depth == 0);
+    let total_files = roots.clone().map(|entry| entry.files).sum();
+    let total_inodes = roots.map(Entry::inodes).sum();
+    Ok(ScanResult::new(result, total_files, total_inodes, duration, config))
+}
+
+/// Walk every path of the configuration, streaming every file and
+/// directory entry to `visit` instead of collecting them.
+///
+/// Files are delivered as soon as they are counted. A directory's totals
+/// are only final once its whole subtree has been walked, so directories
+/// are delivered after the walk, deepest first. `visit` may be called from
+/// any worker thread, calls are serialized. The returned result carries
+/// the totals but no `entries`.
+pub fn scan_with(config: &Config, visit: impl FnMut(&Entry) + Send + Sync) -> anyhow::Result<ScanResult> {
+    let visit = Mutex::new(visit);
+    let total_files = AtomicU64::new(0);
+    let total_inodes = AtomicU64::new(0);
+    let visitor = |entry: &Entry| {
+        if entry.depth == 0 {
+            total_files.fetch_add(entry.files, Ordering::Relaxed);
+            total_inodes.fetch_add(entry.inodes(), Ordering::Relaxed);
+        }
+        let mut visit = visit.lock().unwrap_or_else(PoisonError::into_inner);
+        visit(entry);
+    };
+    let start = Instant::now();
+    let result = walk(config, Some(&visitor))?;
+    let duration = start.elapsed();
+    Ok(ScanResult::new(
+        result,
+        total_files.into_inner(),
+        total_inodes.into_inner(),
+        duration,
+        config,
+    ))
+}
+
+/// Walk the configured paths. With --on-error fail-fast the first error
+/// fails the scan instead of being reported with partial totals.
+fn walk(config: &Config, visit: Option<&Visitor>) -> anyhow::Result<WalkResult> {
+    let result = walk_until_deadline(config, visit)?;
+    if config.traverse_config.on_error == ErrorPolicy::FailFast
+        && let Some(err) = result.errors.first()
+    {
+        anyhow::bail!("Scan stopped on the first error: {err}");
+    }
+    Ok(result)
+}
+
+/// Walk the configured paths, cancelling the walk once --max-time has
+/// elapsed
+fn walk_until_deadline(config: &Config, visit: Option<&Visitor>) -> anyhow::Result<WalkResult> {
+    let walker = walker(config);
+    let Some(max_time) = config.performance_config.max_time else {
+        return walker.walk_with(&config.paths, visit);
+    };
+
+    let cancel = walker.cancel_flag();
+    let (done, finished) = mpsc::channel::<()>();
+    std::thread::scope(|s| {
+        // Wakes up when the walk finishes or the deadline passes, whichever comes first
+        s.spawn(move || {
+            if finished.recv_timeout(max_time) == Err(RecvTimeoutError::Timeout) {
+                log::info!("Max time of {max_time:?} reached, cancelling the walk");
+                cancel.store(true, Ordering::Relaxed);
+            }
+        });
+        let result = walker.walk_with(&config.paths, visit);
+        drop(done);
+        result
+    })
+}
+
+/// Pick the walker for the configured number of threads
+fn walker(config: &Config) -> Box<dyn Walker> {
+    // A single thread gains nothing from the work-stealing machinery
+    if config.performance_config.threads == 1 && config.performance_config.threads_per_device.is_none() {
+        Box::new(SingleThreaded::new(config))
+    } else {
+        Box::new(Multithreaded::new(config))
+    }
+}
+
+impl ScanResult {
+    fn new(
+        result: WalkResult,
+        total_files: u64,
+        total_inodes: u64,
+        duration: Duration,
+        config: &Config,
+    ) -> Self {
+        // Parents may be filtered out of the display, their sizes are kept aside
+        let dir_sizes = match config.output_config.percent {
+            Some(PercentOf::Parent) => result
+                .entries
+                .iter()
+                .filter(|entry| entry.is_dir)
+                .map(|entry| (entry.path.clone(), config.output_config.size_of(entry)))
+                .collect(),
+            _ => HashMap::new(),
+        };
+        // Both a deadline and an error cancel the walk, an error cancels it
+        // only when the policy stops at errors
+        let aborted = result.cancelled
+            && config.traverse_config.on_error != ErrorPolicy::Skip
+            && result.error_counts.total() > 0;
+        // A lost thread and the limits cancel the walk too
+        let cancelled = result.cancelled
+            && result.lost_workers == 0
+            && result.exceeded.is_none()
+            && result.max_entries_reached.is_none();
+        Self {
+            total_bytes: result.total_size(config.output_config.apparent_size),
+            entries: result.entries,
+            roots: config.paths.clone(),
+            total_files,
+            total_inodes,
+            dirs_scanned: result.dirs_processed,
+            files_scanned: result.files_processed,
+            duration,
+            errors: result.error_counts,
+            buffered_errors: result.errors,
+            histogram: result.histogram,
+            extensions: result.extensions,
+            types: result.types,
+            sparse_files: result.sparse_files,
+            mounts: result.mounts,
+            empty: result.empty,
+            duplicates: result.duplicates,
+            non_regular: result.non_regular,
+            estimate: config
+                .performance_config
+                .sample
+                .map(|sample| Estimate::new(sample, result.sample_variance)),
+            dir_sizes,
+            workers: result.workers,
+            timed_out: cancelled && !aborted,
+            aborted,
+            panics: result.panics + result.lost_workers,
+            exceeded: result.exceeded,
+            max_entries_reached: result.max_entries_reached,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::cli::TimeField;
+use crate::core::aggregate::{Entry, blocks_to_bytes};
+use crate::scan::ScanResult;
+use crate::utils::escape_path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Version of the snapshot format written by `save`.
+///
+/// Bump it on any incompatible change and keep `load` able to read the
+/// older versions, so snapshots taken by previous releases still diff.
+///
+/// Version 2 names the time field of the snapshot, version 1 snapshots
+/// are read as keeping the modification time.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// A scan saved with --save, to be compared with a later one with --diff.
+///
+/// Stored as JSON: it is self-describing, so fields can be added without
+/// breaking older snapshots, and it does not depend on the memory layout
+/// of any Rust type.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// When the scan was taken (seconds since epoch)
+    pub timestamp: i64,
+    /// Timestamp kept in the entries, selected with --time: `mtime`,
+    /// `atime` or `ctime`
+    #[serde(default = "default_time_field")]
+    pub time_field: String,
+    pub total_files: u64,
+    pub dirs_scanned: usize,
+    pub files_scanned: usize,
+    pub errors: usize,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// An entry of a snapshot. Paths are escaped with `escape_path`, both
+/// sizes are kept so a diff can use either.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub depth: usize,
+    pub blocks: u64,
+    pub apparent_size: u64,
+    pub files: u64,
+    /// Most recent time in the subtree, of the snapshot's time field
+    #[serde(alias = "mtime")]
+    pub time: i64,
+}
+
+impl SnapshotEntry {
+    /// Size in bytes: apparent size or disk usage
+    pub fn size(&self, apparent: bool) -> u64 {
+        if apparent {
+            self.apparent_size
+        } else {
+            blocks_to_bytes(self.blocks)
+        }
+    }
+}
+
+impl From<&Entry> for SnapshotEntry {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            path: escape_path(&entry.path).into_owned(),
+            is_dir: entry.is_dir,
+            depth: entry.depth,
+            blocks: entry.blocks,
+            apparent_size: entry.apparent_size,
+            files: entry.files,
+            time: entry.time,
+        }
+    }
+}
+
+/// Time field of the snapshots that do not name one
+fn default_time_field() -> String {
+    TimeField::Mtime.name().to_string()
+}
+
+/// Only the version is read first, to pick how to read the rest
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Save the entries and totals of a scan to `path`, with the times of
+/// `time_field`
+pub fn save(path: &Path, result: &ScanResult, time_field: TimeField) -> Result<()> {
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        timestamp: jiff::Timestamp::now().as_second(),
+        time_field: time_field.name().to_string(),
+        total_files: result.total_files,
+        dirs_scanned: result.dirs_scanned,
+        files_scanned: result.files_scanned,
+        errors: result.errors.total(),
+        entries: result.entries.iter().map(SnapshotEntry::from).collect(),
+    };
+    let context = || format!("Failed to save snapshot to {}", path.display());
+    let mut out = BufWriter::new(File::create(path).with_context(context)?);
+    serde_json::to_writer(&mut out, &snapshot).with_context(context)?;
+    out.flush().with_context(context)
+}
+
+/// Load a snapshot saved by this or an older version of fdu
+pub fn load(path: &Path) -> Result<Snapshot> {
+    let context = || format!("Failed to load snapshot {}", path.display());
+    let contents = std::fs::read(path).with_context(context)?;
+    let header: Header = serde_json::from_slice(&contents).with_context(context)?;
+    match header.version {
+        1 | 2 => serde_json::from_slice(&contents).with_context(context),
+        version => anyhow::bail!(
+            "Snapshot {} has version {version}, this fdu reads up to version {SNAPSHOT_VERSION}",
+            path.display()
+        ),
+    }
+}
+
+/// Change in size of a directory between two snapshots
+#[derive(Debug, Serialize)]
+pub struct DirDelta {
+    pub path: String,
+    /// Sizes in bytes, 0 where the directory does not exist
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl DirDelta {
+    pub fn delta(&self) -> i128 {
+        i128::from(self.new_size) - i128::from(self.old_size)
+    }
+}
+
+/// Directories whose size changed between two snapshots, the ones that
+/// grew the most first and the ones that shrank the most last.
+///
+/// Fails when the snapshots keep different times, their entries would
+/// not compare.
+pub fn diff(old: &Snapshot, new: &Snapshot, apparent: bool) -> Result<Vec<DirDelta>> {
+    anyhow::ensure!(
+        old.time_field == new.time_field,
+        "Snapshots keep different times ({} and {}), save both with the same --time",
+        old.time_field,
+        new.time_field
+    );
+    let mut sizes: HashMap<&str, (u64, u64)> = HashMap::new();
+    for entry in old.entries.iter().filter(|entry| entry.is_dir) {
+        sizes.entry(&entry.path).or_default().0 = entry.size(apparent);
+    }
+    for entry in new.entries.iter().filter(|entry| entry.is_dir) {
+        sizes.entry(&entry.path).or_default().1 = entry.size(apparent);
+    }
+
+    let mut deltas: Vec<DirDelta> = sizes
+        .into_iter()
+        .filter(|(_, (old_size, new_size))| old_size != new_size)
+        .map(|(path, (old_size, new_size))| DirDelta {
+            path: path.to_string(),
+            old_size,
+            new_size,
+        })
+        .collect();
+    deltas.sort_by(|a, b| b.delta().cmp(&a.delta()).then_with(|| a.path.cmp(&b.path)));
+    Ok(deltas)
+}
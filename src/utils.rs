@@ -1,4 +1,16 @@
 use anyhow::{Context, Result};
+use jiff::{Timestamp, Zoned, civil};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Parse a size like `512`, `10K`, `1.5MB` or `2GiB` into bytes.
+///
+/// `K`/`KB`, `M`/`MB`... are powers of 1000, `KiB`, `MiB`... powers of
+/// 1024. Suffixes are case-insensitive.
 pub fn parse_size(s: &str) -> Result<u64> {
     let s = s.trim();
     anyhow::ensure!(!s.is_empty(), "Empty size string");
@@ -13,29 +25,189 @@ pub fn parse_size(s: &str) -> Result<u64> {
 
     let (num_part, suffix) = s.split_at(boundary);
 
+    // SI suffixes are powers of 1000, IEC suffixes (KiB, MiB...) powers of 1024
     let multiplier = match suffix.to_uppercase().as_str() {
         "" | "B" => 1,
-        "K" | "KB" => 1_024,
-        "M" | "MB" => 1_024_u64.pow(2),
-        "G" | "GB" => 1_024_u64.pow(3),
-        "T" | "TB" => 1_024_u64.pow(4),
-        "P" | "PB" => 1_024_u64.pow(5),
+        "K" | "KB" => 1_000,
+        "M" | "MB" => 1_000_u64.pow(2),
+        "G" | "GB" => 1_000_u64.pow(3),
+        "T" | "TB" => 1_000_u64.pow(4),
+        "P" | "PB" => 1_000_u64.pow(5),
+        "KIB" => 1_024,
+        "MIB" => 1_024_u64.pow(2),
+        "GIB" => 1_024_u64.pow(3),
+        "TIB" => 1_024_u64.pow(4),
+        "PIB" => 1_024_u64.pow(5),
         _ => anyhow::bail!("Unknown size suffix: {}", suffix),
     };
 
+    // Whole numbers are multiplied exactly, only fractions go through f64
+    if let Ok(num) = num_part.parse::<u64>() {
+        return num.checked_mul(multiplier).context("Size value too large");
+    }
+
     let num: f64 = num_part
         .parse()
-        .with_context(|| format!("Invalid size number: {num_part}'"))?;
+        .with_context(|| format!("Invalid size number: '{num_part}'"))?;
 
     anyhow::ensure!(num >= 0.0, "Size cannot be negative");
 
-    let result = (num * multiplier as f64) as u64;
+    // u64::MAX rounds up to 2^64 as f64, the first value out of range
+    let result = num * multiplier as f64;
+    anyhow::ensure!(result.is_finite() && result < u64::MAX as f64, "Size value too large");
+
+    Ok(result as u64)
+}
+
+/// Parse a duration like `30s`, `5m`, `1.5h`, `7d` or `1w`.
+///
+/// A number without a suffix is in seconds. Days are 24 hours and weeks
+/// 7 days, whatever the calendar says. Suffixes are case-insensitive.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    anyhow::ensure!(!s.is_empty(), "Empty duration string");
+
+    // Find boundary between number and suffix (e.g. 30<boundary>s)
+    let boundary = s.find(|c: char| c.is_alphabetic()).unwrap_or(s.len());
+    anyhow::ensure!(boundary > 0, "No numeric value found");
+
+    let (num_part, suffix) = s.split_at(boundary);
+    let num_part = num_part.trim_end();
 
-    //TODO: need this ?
-    anyhow::ensure!(
-        result as f64 / multiplier as f64 - num < 0.01,
-        "Size value too large"
-    );
+    let seconds: u64 = match suffix.to_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => anyhow::bail!("Unknown duration suffix: {}", suffix),
+    };
+
+    // Whole numbers are multiplied exactly, only fractions go through f64
+    if let Ok(num) = num_part.parse::<u64>() {
+        return num
+            .checked_mul(seconds)
+            .map(Duration::from_secs)
+            .context("Duration value too large");
+    }
+
+    let num: f64 = num_part
+        .parse()
+        .with_context(|| format!("Invalid duration number: '{num_part}'"))?;
+
+    anyhow::ensure!(num >= 0.0, "Duration cannot be negative");
+
+    Duration::try_from_secs_f64(num * seconds as f64).context("Duration value too large")
+}
+
+/// Parse a point in time into seconds since the epoch.
+///
+/// Accepts either an age relative to `now`, a duration as taken by
+/// [`parse_duration`] (`45`, `10m`, `24h`, `7d`, `2w`), a local date or
+/// date-time (`2024-01-31`, `2024-01-31T12:00`), or an RFC 3339 timestamp
+/// (`2024-01-31T12:00:00Z`).
+pub fn parse_time(s: &str, now: &Zoned) -> Result<i64> {
+    let s = s.trim();
+    anyhow::ensure!(!s.is_empty(), "Empty time string");
 
-    Ok(result)
+    if let Ok(timestamp) = s.parse::<Timestamp>() {
+        return Ok(timestamp.as_second());
+    }
+    if let Ok(datetime) = s.parse::<civil::DateTime>() {
+        let zoned = datetime
+            .to_zoned(now.time_zone().clone())
+            .with_context(|| format!("Invalid local time: {s}"))?;
+        return Ok(zoned.timestamp().as_second());
+    }
+
+    let age = parse_duration(s)
+        .with_context(|| format!("Invalid time: {s} (expected an age like 7d or a date like 2024-01-31)"))?;
+    let cutoff = now
+        .timestamp()
+        .checked_sub(age)
+        .with_context(|| format!("Age out of range: {s}"))?;
+    Ok(cutoff.as_second())
+}
+
+/// Look up a numeric id by name in an `/etc/passwd` or `/etc/group` style
+/// database, where the name is the first field and the id the third
+fn lookup_id(database: &str, name: &str) -> Result<u32> {
+    let content =
+        std::fs::read_to_string(database).with_context(|| format!("Failed to read {database}"))?;
+    let id = content
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .with_context(|| format!("No such name in {database}: {name}"))?[2];
+    id.parse()
+        .with_context(|| format!("Invalid id for {name} in {database}: {id}"))
+}
+
+/// Resolve a user name to its uid through `/etc/passwd`
+pub fn lookup_uid(user: &str) -> Result<u32> {
+    lookup_id("/etc/passwd", user)
+}
+
+/// Resolve a group name to its gid through `/etc/group`
+pub fn lookup_gid(group: &str) -> Result<u32> {
+    lookup_id("/etc/group", group)
+}
+
+/// Whether the block device a path is stored on is rotational (a spinning
+/// disk), from `/sys/dev/block/<major>:<minor>`. `None` when it cannot be
+/// told, e.g. for virtual filesystems or outside Linux.
+#[cfg(unix)]
+pub fn is_rotational(path: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let (major, minor) = crate::core::handle::device_numbers(dev);
+    let device = format!("/sys/dev/block/{major}:{minor}");
+    // Partitions have no queue of their own, it belongs to the parent disk
+    let flag = std::fs::read_to_string(format!("{device}/queue/rotational"))
+        .or_else(|_| std::fs::read_to_string(format!("{device}/../queue/rotational")))
+        .ok()?;
+    Some(flag.trim() == "1")
+}
+
+/// Whether the disk a path is stored on is rotational, which cannot be
+/// told outside Linux.
+#[cfg(windows)]
+pub fn is_rotational(_path: &Path) -> Option<bool> {
+    None
+}
+
+/// A path from the raw bytes of its name, as read from a file
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// A path from the bytes of its name, as read from a file. Windows names
+/// are not bytes, those that are not UTF-8 are replaced.
+#[cfg(windows)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Render a path as a string without losing information.
+///
+/// Bytes that are not valid UTF-8 are escaped as `\xNN` and backslashes
+/// are doubled, so two different paths never render the same.
+pub fn escape_path(path: &Path) -> Cow<'_, str> {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    if let Ok(s) = std::str::from_utf8(bytes)
+        && !s.contains('\\')
+    {
+        return Cow::Borrowed(s);
+    }
+
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "\\x{byte:02x}");
+        }
+    }
+    Cow::Owned(escaped)
 }
@@ -0,0 +1,184 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::time::{Duration, Instant};
+
+/// Files SQLite keeps next to a database while writing to it, named after
+/// the database
+const SQLITE_SUFFIXES: [&str; 3] = ["-journal", "-wal", "-shm"];
+
+/// How long the tree must stay quiet before a batch of changes is handed
+/// over, so a file being written triggers one rescan and not hundreds
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Longest a batch is held back while changes keep coming, so a tree that
+/// never goes quiet is still rescanned
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Watches the roots of a scan for changes, with --watch.
+///
+/// Changes under excluded paths are dropped, like the walk drops the
+/// entries, and so are changes to the files fdu writes itself (the output
+/// file, the database, the saved scan and the error report), which would
+/// otherwise trigger a rescan after every report.
+pub struct Watch<'a> {
+    config: &'a Config,
+    events: Receiver<notify::Result<Event>>,
+    /// Each root as given and canonical, the watcher reports canonical
+    /// paths
+    roots: Vec<(PathBuf, PathBuf)>,
+    /// Files written by fdu, canonical
+    own_files: Vec<PathBuf>,
+    // Dropping the watcher stops the events
+    _watcher: RecommendedWatcher,
+}
+
+impl<'a> Watch<'a> {
+    /// Start watching every root, recursively
+    pub fn new(config: &'a Config) -> Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).context("Failed to start the file watcher")?;
+        for path in &config.paths {
+            watcher
+                .watch(path, RecursiveMode::Recursive)
+                .with_context(|| format!("Failed to watch {}", path.display()))?;
+        }
+        let roots = config
+            .paths
+            .iter()
+            .filter_map(|path| Some((path.clone(), path.canonicalize().ok()?)))
+            .collect();
+        let output = &config.output_config;
+        let own_files = [&output.output_file, &output.db, &output.save, &output.error_json]
+            .into_iter()
+            .flatten()
+            .filter_map(|path| canonical(path))
+            .collect();
+        Ok(Self {
+            config,
+            events,
+            roots,
+            own_files,
+            _watcher: watcher,
+        })
+    }
+
+    /// Wait for files to change, and return the topmost paths changed, below
+    /// the roots as given: a changed directory stands for everything below
+    /// it. Only fails when the watcher itself stops.
+    pub fn wait(&self) -> Result<Vec<PathBuf>> {
+        let mut changed = BTreeSet::new();
+        // Block until the first relevant change, then gather the ones
+        // following it until the tree settles
+        while changed.is_empty() {
+            let event = self.events.recv().context("The file watcher stopped")?;
+            self.collect(event, &mut changed);
+        }
+        let deadline = Instant::now() + MAX_DELAY;
+        loop {
+            let timeout = SETTLE.min(deadline.saturating_duration_since(Instant::now()));
+            match self.events.recv_timeout(timeout) {
+                Ok(event) => self.collect(event, &mut changed),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("The file watcher stopped"),
+            }
+        }
+        Ok(topmost(changed))
+    }
+
+    /// Add the paths of an event to `changed`, unless they do not count
+    fn collect(&self, event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+        let event = match event {
+            Ok(event) => event,
+            // Like a lost event queue: a change may have been missed,
+            // rescanning everything catches up with it
+            Err(err) => {
+                log::warn!("File watcher error: {err}");
+                changed.extend(self.config.paths.iter().cloned());
+                return;
+            }
+        };
+        // Reads do not change sizes, and the scans read every directory
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in event.paths {
+            if self.is_own_file(&path) {
+                continue;
+            }
+            let path = self.as_given(path);
+            if !self.is_excluded(&path) {
+                log::debug!("Changed: {}", path.display());
+                changed.insert(path);
+            }
+        }
+    }
+
+    /// A path reported by the watcher, below the root it was given as
+    fn as_given(&self, path: PathBuf) -> PathBuf {
+        for (given, canonical) in &self.roots {
+            if let Ok(rest) = path.strip_prefix(canonical) {
+                return given.join(rest);
+            }
+        }
+        path
+    }
+
+    /// Whether a path is in a subtree the walk leaves out
+    fn is_excluded(&self, path: &Path) -> bool {
+        let filter = &self.config.filter_config;
+        if !filter.has_patterns() {
+            return false;
+        }
+        let Some(root) = self.config.paths.iter().find(|root| path.starts_with(root)) else {
+            return false;
+        };
+        path.ancestors()
+            .take_while(|ancestor| ancestor.starts_with(root) && *ancestor != root)
+            .any(|ancestor| filter.is_excluded(ancestor))
+    }
+
+    /// Whether a path is one of the files fdu writes, or a file SQLite
+    /// keeps next to its database
+    fn is_own_file(&self, path: &Path) -> bool {
+        if self.own_files.is_empty() {
+            return false;
+        }
+        let Some(path) = canonical(path) else {
+            return false;
+        };
+        let path = path.as_os_str().as_encoded_bytes();
+        self.own_files
+            .iter()
+            .any(|own| match path.strip_prefix(own.as_os_str().as_encoded_bytes()) {
+                Some(rest) => rest.is_empty() || SQLITE_SUFFIXES.iter().any(|suffix| rest == suffix.as_bytes()),
+                None => false,
+            })
+    }
+}
+
+/// Canonical form of a path that may not exist yet: its parent directory
+/// resolved, and its name
+fn canonical(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Some(parent.canonicalize().ok()?.join(name))
+}
+
+/// Drop the paths below another path of the set
+fn topmost(paths: BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    let mut topmost: Vec<PathBuf> = Vec::new();
+    // Sorted, a path comes right after the paths above it
+    for path in paths {
+        if topmost.last().is_none_or(|last| !path.starts_with(last)) {
+            topmost.push(path);
+        }
+    }
+    topmost
+}
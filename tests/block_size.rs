@@ -0,0 +1,58 @@
+//! The block size comes from `--block-size`, then from the environment
+//! like du, then from the default.
+//!
+//! Everything runs in a single test: the environment is shared by the
+//! whole process and tests run in parallel.
+
+use clap::Parser;
+use fdu::cli::{Cli, SizeFormat};
+use fdu::config::Config;
+
+const VARS: [&str; 4] = ["DU_BLOCK_SIZE", "BLOCK_SIZE", "BLOCKSIZE", "POSIXLY_CORRECT"];
+
+/// Size format and block size resolved for `args`, with only `env` set
+fn resolve(env: &[(&str, &str)], args: &[&str]) -> (SizeFormat, Option<u64>) {
+    // SAFETY: no other thread of this test binary reads or writes the
+    // environment, this is its only test
+    unsafe {
+        for var in VARS {
+            std::env::remove_var(var);
+        }
+        for (var, value) in env {
+            std::env::set_var(var, value);
+        }
+    }
+    let cli = Cli::parse_from(["fdu"].iter().chain(args).chain(&["."]));
+    let config = Config::from_cli(&cli).unwrap().output_config;
+    (config.size_format, config.block_size)
+}
+
+#[test]
+fn block_size_precedence() {
+    assert_eq!(resolve(&[], &[]), (SizeFormat::Human, None));
+
+    // Each variable on its own
+    assert_eq!(resolve(&[("DU_BLOCK_SIZE", "4K")], &[]).1, Some(4_000));
+    assert_eq!(resolve(&[("BLOCK_SIZE", "1KiB")], &[]).1, Some(1_024));
+    assert_eq!(resolve(&[("BLOCKSIZE", "2048")], &[]).1, Some(2_048));
+    assert_eq!(resolve(&[("POSIXLY_CORRECT", "1")], &[]).1, Some(512));
+    assert_eq!(resolve(&[("BLOCK_SIZE", "si")], &[]), (SizeFormat::Si, None));
+    // Set but empty is unset
+    assert_eq!(resolve(&[("DU_BLOCK_SIZE", ""), ("BLOCK_SIZE", "1M")], &[]).1, Some(1_000_000));
+
+    // DU_BLOCK_SIZE first, then BLOCK_SIZE, and any of them over POSIXLY_CORRECT
+    let all = [("DU_BLOCK_SIZE", "4K"), ("BLOCK_SIZE", "1M"), ("POSIXLY_CORRECT", "1")];
+    assert_eq!(resolve(&all, &[]).1, Some(4_000));
+    assert_eq!(resolve(&all[1..], &[]).1, Some(1_000_000));
+
+    // The command line over the environment
+    assert_eq!(resolve(&all, &["-B", "100"]).1, Some(100));
+    assert_eq!(resolve(&all, &["--human-readable"]), (SizeFormat::Human, None));
+    assert_eq!(resolve(&all, &["--format", "bytes"]), (SizeFormat::Bytes, None));
+
+    // An invalid value is an error, not silently ignored
+    let cli = Cli::parse_from(["fdu", "."]);
+    unsafe { std::env::set_var("DU_BLOCK_SIZE", "lots") };
+    let err = Config::from_cli(&cli).unwrap_err();
+    assert!(format!("{err:#}").contains("DU_BLOCK_SIZE"), "{err:#}");
+}
@@ -0,0 +1,121 @@
+//! Totals must match du on the same tree: GNU du, and the system du on
+//! macOS and FreeBSD.
+//!
+//! Skipped when du is not installed.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process::Command;
+
+/// Build a tree with files of various sizes, nested directories, a
+/// directory with thousands of entries, hard links and a sparse file
+fn build_fixture(root: &Path) {
+    let sizes = [0, 1, 511, 512, 4095, 4096, 4097, 100_000, 1 << 20];
+    for (level, dir) in ["", "a", "a/b", "a/b/c", "d"].iter().enumerate() {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (i, size) in sizes.iter().enumerate() {
+            fs::write(dir.join(format!("file{i}")), vec![level as u8; *size]).unwrap();
+        }
+    }
+    fs::create_dir(root.join("empty")).unwrap();
+
+    // A directory with thousands of entries, itself several blocks
+    fs::create_dir(root.join("fat")).unwrap();
+    for i in 0..5000 {
+        File::create(root.join(format!("fat/a-file-name-long-enough-to-fill-blocks-{i:05}"))).unwrap();
+    }
+
+    // Hard links are counted once, in and across directories
+    fs::hard_link(root.join("file7"), root.join("link7")).unwrap();
+    fs::hard_link(root.join("a/b/file8"), root.join("d/link8")).unwrap();
+
+    // A sparse file, 64 MiB apparent with a single 4 KiB block written
+    let mut sparse = File::create(root.join("d/sparse")).unwrap();
+    sparse.set_len(64 << 20).unwrap();
+    sparse.seek(SeekFrom::Start(32 << 20)).unwrap();
+    sparse.write_all(&[1; 4096]).unwrap();
+}
+
+/// Run du on `path` with `args`, returning the total it prints, or `None`
+/// if GNU du is not available
+fn du(path: &Path, args: &[&str]) -> Option<u64> {
+    let output = Command::new("du").args(args).arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    stdout.split_whitespace().next()?.parse().ok()
+}
+
+/// Scan `path` with fdu and return the grand total in bytes
+fn fdu(path: &Path, args: &[&str]) -> u64 {
+    let path = path.to_str().unwrap();
+    let cli = Cli::parse_from(["fdu"].iter().chain(args).chain([&path]));
+    let config = Config::from_cli(&cli).unwrap();
+    fdu::scan(&config).unwrap().total_bytes
+}
+
+#[test]
+fn disk_usage_matches_du() {
+    let fixture = tempfile::tempdir().unwrap();
+    build_fixture(fixture.path());
+    let Some(blocks) = du(fixture.path(), &["-s", "--block-size=512"]) else {
+        eprintln!("GNU du not available, skipping");
+        return;
+    };
+
+    for threads in ["1", "4"] {
+        assert_eq!(fdu(fixture.path(), &["-s", "-j", threads]), blocks * 512, "with {threads} threads");
+    }
+}
+
+#[test]
+fn apparent_size_matches_du() {
+    let fixture = tempfile::tempdir().unwrap();
+    build_fixture(fixture.path());
+    let Some(bytes) = du(fixture.path(), &["-s", "--bytes"]) else {
+        eprintln!("GNU du not available, skipping");
+        return;
+    };
+
+    for threads in ["1", "4"] {
+        assert_eq!(
+            fdu(fixture.path(), &["-s", "--apparent-size", "-j", threads]),
+            bytes,
+            "with {threads} threads"
+        );
+    }
+}
+
+/// Run the BSD du of macOS and FreeBSD on `path`, returning its total in
+/// 512-byte blocks, or `None` if it is not available. It takes none of the
+/// GNU options, its unit comes from `BLOCKSIZE`.
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn bsd_du(path: &Path) -> Option<u64> {
+    let output = Command::new("du").env("BLOCKSIZE", "512").arg("-s").arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    stdout.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+#[test]
+fn disk_usage_matches_bsd_du() {
+    let fixture = tempfile::tempdir().unwrap();
+    build_fixture(fixture.path());
+    let Some(blocks) = bsd_du(fixture.path()) else {
+        eprintln!("du not available, skipping");
+        return;
+    };
+
+    for threads in ["1", "4"] {
+        assert_eq!(fdu(fixture.path(), &["-s", "-j", threads]), blocks * 512, "with {threads} threads");
+    }
+}
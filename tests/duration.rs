@@ -0,0 +1,67 @@
+//! Durations given on the command line, like `--max-time 5m`, and the
+//! ages of `--newer-than` and `--older-than` that share their suffixes.
+
+use fdu::utils::{parse_duration, parse_time};
+use jiff::Zoned;
+use std::time::Duration;
+
+#[test]
+fn durations_with_suffixes() {
+    assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+    assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+    assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(5 * 60));
+    assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(2 * 3600));
+    assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(7 * 86_400));
+    assert_eq!(parse_duration("1w").unwrap(), Duration::from_secs(7 * 86_400));
+    assert_eq!(parse_duration(" 10 M ").unwrap(), Duration::from_secs(10 * 60));
+}
+
+#[test]
+fn fractional_durations() {
+    assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(90 * 60));
+    assert_eq!(parse_duration("0.5s").unwrap(), Duration::from_millis(500));
+    assert_eq!(parse_duration(".25m").unwrap(), Duration::from_secs(15));
+}
+
+#[test]
+fn invalid_durations() {
+    for invalid in ["", "  ", "s", "-5m", "-0.5h", "5y", "5ms", "1.2.3s", "abc", "99999999999999999999w"] {
+        assert!(parse_duration(invalid).is_err(), "{invalid:?} should not parse");
+    }
+    assert!(format!("{:#}", parse_duration("5y").unwrap_err()).contains("Unknown duration suffix"));
+    assert!(format!("{:#}", parse_duration("-5m").unwrap_err()).contains("negative"));
+}
+
+#[test]
+fn ages_share_the_duration_suffixes() {
+    let now: Zoned = "2024-06-01T12:00:00+00:00[UTC]".parse().unwrap();
+    let noon = now.timestamp().as_second();
+    let table: &[(&str, i64)] = &[
+        ("45", 45),
+        ("30s", 30),
+        ("10M", 10 * 60),
+        ("10m", 10 * 60),
+        ("1.5h", 90 * 60),
+        ("7d", 7 * 86_400),
+        ("2w", 14 * 86_400),
+    ];
+    for (age, seconds) in table {
+        assert_eq!(parse_time(age, &now).unwrap(), noon - seconds, "{age:?}");
+    }
+}
+
+#[test]
+fn absolute_times() {
+    let now: Zoned = "2024-06-01T12:00:00+00:00[UTC]".parse().unwrap();
+    assert_eq!(parse_time("2024-01-31", &now).unwrap(), 1_706_659_200);
+    assert_eq!(parse_time("2024-01-31T12:00", &now).unwrap(), 1_706_702_400);
+    assert_eq!(parse_time("2024-01-31T12:00:00+01:00", &now).unwrap(), 1_706_698_800);
+}
+
+#[test]
+fn invalid_times() {
+    let now: Zoned = "2024-06-01T12:00:00+00:00[UTC]".parse().unwrap();
+    for invalid in ["", "-5m", "5y", "1y 6mo", "2024-13-01", "abc"] {
+        assert!(parse_time(invalid, &now).is_err(), "{invalid:?} should not parse");
+    }
+}
@@ -0,0 +1,112 @@
+//! Exclude patterns from files and flags, and their precedence over
+//! include patterns, and the size threshold of displayed entries.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use fdu::output::filter_entries;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Scan `root` with `args`, returning the file paths counted, relative to `root`
+fn counted_files(root: &Path, args: &[&str]) -> Vec<PathBuf> {
+    let cli = Cli::parse_from(["fdu", "-f"].iter().chain(args).chain([&root.to_str().unwrap()]));
+    let result = fdu::scan(&Config::from_cli(&cli).unwrap()).unwrap();
+    let mut files: Vec<PathBuf> = result
+        .entries
+        .into_iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| entry.path.strip_prefix(root).unwrap().to_path_buf())
+        .collect();
+    files.sort();
+    files
+}
+
+fn build_fixture(root: &Path) {
+    for file in ["keep.rs", "notes.txt", "build/out.o", "build/keep.rs", "cache/blob"] {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "data").unwrap();
+    }
+}
+
+#[test]
+fn exclude_from_files_and_flags_combine() {
+    let fixture = tempfile::tempdir().unwrap();
+    let root = fixture.path().join("tree");
+    build_fixture(&root);
+    let first = fixture.path().join("first");
+    let second = fixture.path().join("second");
+    fs::write(&first, "# build artifacts\n/build$\n\n").unwrap();
+    fs::write(&second, "\\.txt$\n").unwrap();
+
+    let files = counted_files(
+        &root,
+        &[
+            "--exclude-from",
+            first.to_str().unwrap(),
+            "--exclude-from",
+            second.to_str().unwrap(),
+            "--exclude",
+            "/cache$",
+        ],
+    );
+    assert_eq!(files, [Path::new("keep.rs")]);
+}
+
+#[test]
+fn excludes_take_precedence_over_includes() {
+    let fixture = tempfile::tempdir().unwrap();
+    let root = fixture.path().join("tree");
+    build_fixture(&root);
+    let excludes = fixture.path().join("excludes");
+    fs::write(&excludes, "/build/\n").unwrap();
+
+    let files = counted_files(&root, &["--include", "\\.rs$", "--exclude-from", excludes.to_str().unwrap()]);
+    assert_eq!(files, [Path::new("keep.rs")]);
+}
+
+#[test]
+fn invalid_pattern_names_file_and_line() {
+    let fixture = tempfile::tempdir().unwrap();
+    let excludes = fixture.path().join("excludes");
+    fs::write(&excludes, "# comment\nvalid\n(unclosed\n").unwrap();
+
+    let cli = Cli::parse_from(["fdu", "--exclude-from", excludes.to_str().unwrap(), "."]);
+    let err = Config::from_cli(&cli).unwrap_err();
+    assert_eq!(err.to_string(), format!("Invalid exclude pattern at {}:3", excludes.display()));
+}
+
+/// Scan `root` with `args` and drop the entries that are not displayed,
+/// returning the apparent size of each directory left, relative to `root`
+fn displayed_dirs(root: &Path, args: &[&str]) -> Vec<(PathBuf, u64)> {
+    let cli = Cli::parse_from(["fdu", "--apparent-size"].iter().chain(args).chain([&root.to_str().unwrap()]));
+    let config = Config::from_cli(&cli).unwrap();
+    let mut entries = fdu::scan(&config).unwrap().entries;
+    filter_entries(&mut entries, &config.output_config);
+    let mut dirs: Vec<(PathBuf, u64)> = entries
+        .into_iter()
+        .map(|entry| (entry.path.strip_prefix(root).unwrap().to_path_buf(), entry.apparent_size))
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+#[test]
+fn threshold_hides_small_dirs_but_not_their_size() {
+    let fixture = tempfile::tempdir().unwrap();
+    let root = fixture.path().join("tree");
+    for (dir, size) in [("small", 500_000), ("large", 1_500_000)] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+        fs::write(root.join(dir).join("file"), vec![0; size]).unwrap();
+    }
+
+    let all = displayed_dirs(&root, &[]);
+    let paths: Vec<&Path> = all.iter().map(|(path, _)| path.as_path()).collect();
+    assert_eq!(paths, [Path::new(""), Path::new("large"), Path::new("small")]);
+
+    // The 500K directory is hidden, and still counted in its parent
+    let above = displayed_dirs(&root, &["--threshold", "1M"]);
+    assert_eq!(above, [all[0].clone(), all[1].clone()]);
+    assert!(above[0].1 >= 2_000_000);
+}
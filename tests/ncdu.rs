@@ -0,0 +1,54 @@
+//! The ncdu export flags the directories that failed to read and the
+//! files with hard links.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use fdu::error::WalkError;
+use fdu::output::ncdu::write_tree;
+use serde_json::Value;
+use std::fs;
+use std::io;
+
+/// Find the information object of the entry named `name` in an ncdu
+/// export tree, directories being arrays led by their information
+fn find<'a>(node: &'a Value, name: &str) -> Option<&'a Value> {
+    match node {
+        Value::Array(items) => {
+            if items[0]["name"] == name {
+                return Some(&items[0]);
+            }
+            items[1..].iter().find_map(|child| find(child, name))
+        }
+        info => (info["name"] == name).then_some(info),
+    }
+}
+
+#[test]
+fn read_errors_and_hard_links_are_flagged() {
+    let fixture = tempfile::tempdir().unwrap();
+    let root = fixture.path().join("tree");
+    fs::create_dir_all(root.join("locked")).unwrap();
+    fs::write(root.join("file"), "data").unwrap();
+    fs::write(root.join("linked"), "data").unwrap();
+    fs::hard_link(root.join("linked"), fixture.path().join("outside")).unwrap();
+
+    let cli = Cli::parse_from(["fdu", "-o", "ncdu", root.to_str().unwrap()]);
+    let config = Config::from_cli(&cli).unwrap();
+    let result = fdu::scan(&config).unwrap();
+    // Given directly, root can read any directory
+    let errors = [WalkError::io(&root.join("locked"), io::Error::from(io::ErrorKind::PermissionDenied))];
+    let mut out = Vec::new();
+    write_tree(&mut out, &result.entries, &errors).unwrap();
+    let export: Value = serde_json::from_slice(&out).unwrap();
+    let tree = &export[3];
+
+    assert_eq!(find(tree, "locked").unwrap()["read_error"], true);
+    assert!(find(tree, "file").unwrap().get("read_error").is_none());
+
+    let linked = find(tree, "linked").unwrap();
+    assert_eq!(linked["hlnkc"], true);
+    assert!(linked["ino"].as_u64().is_some());
+    let file = find(tree, "file").unwrap();
+    assert!(file.get("hlnkc").is_none() && file.get("ino").is_none());
+}
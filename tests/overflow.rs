@@ -0,0 +1,38 @@
+//! Block totals converted to bytes saturate instead of wrapping around,
+//! on filesystems of many petabytes.
+
+use fdu::core::aggregate::{BLOCK_UNIT, blocks_to_bytes};
+use fdu::core::walker::WalkResult;
+
+/// The largest block count whose size in bytes fits in a u64
+const MAX_BLOCKS: u64 = u64::MAX / BLOCK_UNIT;
+
+#[test]
+fn block_counts_near_the_limit() {
+    assert_eq!(blocks_to_bytes(MAX_BLOCKS - 1), (MAX_BLOCKS - 1) * BLOCK_UNIT);
+    assert_eq!(blocks_to_bytes(MAX_BLOCKS), MAX_BLOCKS * BLOCK_UNIT);
+    assert_eq!(blocks_to_bytes(MAX_BLOCKS + 1), u64::MAX);
+    assert_eq!(blocks_to_bytes(u64::MAX), u64::MAX);
+}
+
+#[test]
+fn walk_totals_saturate() {
+    let total = |total_blocks| {
+        WalkResult {
+            total_blocks,
+            total_apparent_size: 1234,
+            ..Default::default()
+        }
+        .total_size(false)
+    };
+    assert_eq!(total(MAX_BLOCKS), MAX_BLOCKS * BLOCK_UNIT);
+    assert_eq!(total(MAX_BLOCKS + 1), u64::MAX);
+    assert_eq!(total(u64::MAX), u64::MAX);
+
+    let apparent = WalkResult {
+        total_blocks: u64::MAX,
+        total_apparent_size: 1234,
+        ..Default::default()
+    };
+    assert_eq!(apparent.total_size(true), 1234);
+}
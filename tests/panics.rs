@@ -0,0 +1,76 @@
+//! A panic while scanning is reported, and loses only the directory it
+//! happened in. Panics are injected through the visitor, which is called
+//! for every file as the walk counts it.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Scan `root` with `args`, panicking when the file named `boom` is
+/// counted. Returns the number of panics reported and the files counted.
+fn scan_with_panic(root: &Path, args: &[&str]) -> (usize, Vec<PathBuf>) {
+    let (panics, files, _) = scan_with_totals(root, args);
+    (panics, files)
+}
+
+/// Like `scan_with_panic`, also returning the grand total in bytes and
+/// the size of the root entry, which should match it
+fn scan_with_totals(root: &Path, args: &[&str]) -> (usize, Vec<PathBuf>, (u64, u64)) {
+    let cli = Cli::parse_from(["fdu"].iter().chain(args).copied().chain([root.to_str().unwrap()]));
+    let config = Config::from_cli(&cli).unwrap();
+    let mut files = Vec::new();
+    let mut root_size = 0;
+    let result = fdu::scan_with(&config, |entry| {
+        if entry.path.ends_with("boom") {
+            panic!("injected panic");
+        }
+        if !entry.is_dir {
+            files.push(entry.path.clone());
+        }
+        if entry.depth == 0 {
+            root_size += entry.size(config.output_config.apparent_size);
+        }
+    })
+    .unwrap();
+    files.sort();
+    (result.panics, files, (result.total_bytes, root_size))
+}
+
+#[test]
+fn panics_are_reported_and_the_rest_is_counted() {
+    let fixture = tempfile::tempdir().unwrap();
+    for dir in ["a", "b", "c"] {
+        fs::create_dir(fixture.path().join(dir)).unwrap();
+        fs::write(fixture.path().join(dir).join("file"), "data").unwrap();
+    }
+    fs::write(fixture.path().join("b/boom"), "data").unwrap();
+
+    for threads in ["1", "4"] {
+        let (panics, files) = scan_with_panic(fixture.path(), &["-j", threads]);
+        assert_eq!(panics, 1, "with {threads} threads");
+        // Only the directory of the panic is cut short
+        assert!(files.contains(&fixture.path().join("a/file")), "with {threads} threads");
+        assert!(files.contains(&fixture.path().join("c/file")), "with {threads} threads");
+    }
+
+    let (panics, _) = scan_with_panic(&fixture.path().join("a"), &[]);
+    assert_eq!(panics, 0);
+}
+
+#[test]
+fn totals_leave_out_the_directory_of_the_panic() {
+    let fixture = tempfile::tempdir().unwrap();
+    for dir in ["a", "b"] {
+        fs::create_dir(fixture.path().join(dir)).unwrap();
+        fs::write(fixture.path().join(dir).join("file"), vec![0; 100_000]).unwrap();
+    }
+    fs::write(fixture.path().join("b/boom"), "data").unwrap();
+
+    for args in [&["-j", "1"][..], &["-j", "4"], &["-j", "4", "--apparent-size"]] {
+        let (panics, _, (total, root_size)) = scan_with_totals(fixture.path(), args);
+        assert_eq!(panics, 1, "with {args:?}");
+        assert_eq!(total, root_size, "with {args:?}");
+    }
+}
@@ -0,0 +1,91 @@
+//! Input paths counted twice are dropped before walking, input paths
+//! that are symlinks are followed.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Input paths kept by the configuration
+fn config_paths(paths: &[&Path]) -> Vec<PathBuf> {
+    let cli = Cli::parse_from(["fdu".as_ref()].into_iter().chain(paths.iter().map(|path| path.as_os_str())));
+    Config::from_cli(&cli).unwrap().paths
+}
+
+// Creating symlinks takes a privilege on Windows
+#[cfg(unix)]
+#[test]
+fn duplicate_paths_are_counted_once() {
+    let fixture = tempfile::tempdir().unwrap();
+    let a = fixture.path().join("a");
+    let b = fixture.path().join("b");
+    fs::create_dir(&a).unwrap();
+    fs::create_dir(&b).unwrap();
+    std::os::unix::fs::symlink(&a, fixture.path().join("link")).unwrap();
+
+    assert_eq!(config_paths(&[&a, &b, &a]), [a.as_path(), b.as_path()]);
+    assert_eq!(config_paths(&[&a, &fixture.path().join("link")]), [a.as_path()]);
+    assert_eq!(config_paths(&[&a, &fixture.path().join("./a")]), [a.as_path()]);
+}
+
+#[test]
+fn nested_paths_are_counted_in_their_ancestor() {
+    let fixture = tempfile::tempdir().unwrap();
+    let a = fixture.path().join("a");
+    let nested = a.join("b/c");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(nested.join("file"), "data").unwrap();
+
+    assert_eq!(config_paths(&[&a, &nested]), [a.as_path()]);
+    assert_eq!(config_paths(&[&nested, &a]), [a.as_path()]);
+    assert_eq!(config_paths(&[&nested.join("file"), &a]), [a.as_path()]);
+
+    // Siblings sharing a name prefix are not nested
+    let ab = fixture.path().join("ab");
+    fs::create_dir(&ab).unwrap();
+    assert_eq!(config_paths(&[&a, &ab]), [a.as_path(), ab.as_path()]);
+}
+
+#[test]
+fn nested_paths_do_not_change_the_total() {
+    let fixture = tempfile::tempdir().unwrap();
+    let a = fixture.path().join("a");
+    fs::create_dir_all(a.join("b")).unwrap();
+    fs::write(a.join("b/file"), vec![0; 100_000]).unwrap();
+
+    let total = |paths: &[&Path]| {
+        let args = ["fdu", "-s", "--apparent-size"].iter().map(AsRef::as_ref);
+        let cli = Cli::parse_from(args.chain(paths.iter().map(|path| path.as_os_str())));
+        fdu::scan(&Config::from_cli(&cli).unwrap()).unwrap().total_bytes
+    };
+    assert_eq!(total(&[&a, &a.join("b"), &a]), total(&[&a]));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_roots_are_followed() {
+    let fixture = tempfile::tempdir().unwrap();
+    let dir = fixture.path().join("dir");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/file"), vec![0; 100_000]).unwrap();
+    fs::write(dir.join("top"), vec![0; 5_000]).unwrap();
+    let dir_link = fixture.path().join("dir-link");
+    let file_link = fixture.path().join("file-link");
+    std::os::unix::fs::symlink(&dir, &dir_link).unwrap();
+    std::os::unix::fs::symlink(dir.join("top"), &file_link).unwrap();
+
+    let scan = |path: &Path| {
+        let cli = Cli::parse_from(["fdu".as_ref(), "-a".as_ref(), "--apparent-size".as_ref(), path.as_os_str()]);
+        fdu::scan(&Config::from_cli(&cli).unwrap()).unwrap()
+    };
+    let linked = scan(&dir_link);
+    assert_eq!(linked.total_bytes, scan(&dir).total_bytes);
+    assert_eq!(linked.total_files, 2);
+    assert_eq!(linked.errors.total(), 0);
+    // Entries are listed under the link as given
+    assert!(linked.entries.iter().all(|entry| entry.path.starts_with(&dir_link)));
+
+    let linked = scan(&file_link);
+    assert_eq!((linked.total_bytes, linked.total_files), (5_000, 1));
+}
@@ -0,0 +1,43 @@
+//! The porcelain output is a frozen format, any change to it must fail
+//! here.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use fdu::output;
+use std::fs;
+use std::path::Path;
+
+/// Scan `root` with `args` and write the report as the binary does
+fn report(root: &Path, args: &[&str]) -> Vec<u8> {
+    let args = ["fdu", "--porcelain", "--path-style", "relative"].iter().chain(args);
+    let cli = Cli::parse_from(args.map(AsRef::as_ref).chain([root.as_os_str()]));
+    let config = Config::from_cli(&cli).unwrap();
+    let mut result = fdu::scan(&config).unwrap();
+    output::filter_entries(&mut result.entries, &config.output_config);
+    output::sort_entries(&mut result.entries, &config.output_config);
+    let mut out = Vec::new();
+    output::write_report(&mut out, &result, &config.output_config).unwrap();
+    out
+}
+
+#[test]
+fn porcelain_output_is_frozen() {
+    let fixture = tempfile::tempdir().unwrap();
+    let root = fixture.path();
+    fs::create_dir_all(root.join("b/nested")).unwrap();
+    fs::create_dir(root.join("a")).unwrap();
+    fs::write(root.join("a/small"), vec![0; 10]).unwrap();
+    fs::write(root.join("b/big"), vec![0; 1_234_567]).unwrap();
+    fs::write(root.join("b/nested/name with spaces"), vec![0; 100]).unwrap();
+    fs::write(root.join("top"), vec![0; 5]).unwrap();
+
+    // Sizes in plain bytes, paths in path order whatever the sort, no
+    // header and no total
+    let args = ["--files-only", "--apparent-size", "--sort", "size", "--total", "--thousands"];
+    let expected = "10\ta/small\n1234567\tb/big\n100\tb/nested/name with spaces\n5\ttop\n";
+    assert_eq!(String::from_utf8(report(root, &args)).unwrap(), expected);
+
+    let expected = expected.replace('\n', "\0");
+    assert_eq!(String::from_utf8(report(root, &[&args[..], &["--null"]].concat())).unwrap(), expected);
+}
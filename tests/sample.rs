@@ -0,0 +1,12 @@
+//! Files without an inode number are sampled by a hash of their name,
+//! which must not change between runs or Rust versions.
+
+use fdu::core::sample::name_key;
+use std::ffi::OsStr;
+
+#[test]
+fn name_key_is_fnv1a() {
+    assert_eq!(name_key(OsStr::new("")), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(name_key(OsStr::new("a")), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(name_key(OsStr::new("foobar")), 0x8594_4171_f739_67e8);
+}
@@ -0,0 +1,35 @@
+//! Jobs past the local queue cap of a worker go to the global queue,
+//! where the other workers take them.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use fdu::core::walker::{Multithreaded, Walker};
+use std::fs;
+use std::path::Path;
+
+const SUBDIRS: usize = 64;
+
+fn build_fixture(root: &Path) {
+    for i in 0..SUBDIRS {
+        let dir = root.join(format!("dir{i:02}"));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("file"), "data").unwrap();
+    }
+}
+
+#[test]
+fn jobs_past_the_cap_are_redistributed() {
+    let fixture = tempfile::tempdir().unwrap();
+    build_fixture(fixture.path());
+
+    // A single worker of the work-stealing walker, which keeps one job
+    // of the root's subdirectories
+    let cli = Cli::parse_from(["fdu", "-s", "-j", "1", "--local-queue-cap", "1", fixture.path().to_str().unwrap()]);
+    let config = Config::from_cli(&cli).unwrap();
+    let result = Multithreaded::new(&config).walk(&config.paths).unwrap();
+    let redistributed: usize = result.workers.iter().map(|stats| stats.jobs_redistributed).sum();
+    assert!(redistributed > 0);
+    let root = result.entries.iter().find(|entry| entry.depth == 0).unwrap();
+    assert_eq!(root.files, SUBDIRS as u64);
+}
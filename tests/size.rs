@@ -0,0 +1,84 @@
+//! Sizes given on the command line, like `--threshold 10M`.
+
+use fdu::utils::parse_size;
+
+#[test]
+fn si_and_binary_units() {
+    let table: &[(&str, u64)] = &[
+        ("0", 0),
+        ("512", 512),
+        ("512B", 512),
+        ("1K", 1_000),
+        ("1KB", 1_000),
+        ("1KiB", 1_024),
+        ("1M", 1_000_000),
+        ("1MB", 1_000_000),
+        ("1MiB", 1 << 20),
+        ("1G", 1_000_000_000),
+        ("1GB", 1_000_000_000),
+        ("1GiB", 1 << 30),
+        ("1T", 1_000_000_000_000),
+        ("1TB", 1_000_000_000_000),
+        ("1TiB", 1 << 40),
+        ("1P", 1_000_000_000_000_000),
+        ("1PB", 1_000_000_000_000_000),
+        ("1PiB", 1 << 50),
+        ("4K", 4_000),
+        ("4KiB", 4_096),
+        // Suffixes in any case
+        ("10b", 10),
+        ("2k", 2_000),
+        ("2kb", 2_000),
+        ("2Kb", 2_000),
+        ("2kib", 2_048),
+        ("2KIB", 2_048),
+        ("3gib", 3 << 30),
+        ("3Gb", 3_000_000_000),
+        // Fractions and spaces around the size
+        ("2.5K", 2_500),
+        ("2.5KiB", 2_560),
+        ("0.25GiB", 1 << 28),
+        (".5M", 500_000),
+        (" 7M ", 7_000_000),
+    ];
+    for (size, bytes) in table {
+        assert_eq!(parse_size(size).unwrap(), *bytes, "{size:?}");
+    }
+}
+
+#[test]
+fn invalid_sizes() {
+    for invalid in ["", "  ", "K", "KiB", "-1K", "-0.5M", "5X", "5KiBB", "5Ki", "1.2.3K", "abc"] {
+        assert!(parse_size(invalid).is_err(), "{invalid:?} should not parse");
+    }
+    assert!(format!("{:#}", parse_size("5X").unwrap_err()).contains("Unknown size suffix"));
+}
+
+#[test]
+fn fractional_sizes() {
+    assert_eq!(parse_size("1.5M").unwrap(), 1_500_000);
+    assert_eq!(parse_size("1.5MiB").unwrap(), 1_572_864);
+    assert_eq!(parse_size("0.5K").unwrap(), 500);
+}
+
+#[test]
+fn sizes_too_large() {
+    let too_large = |size| format!("{:#}", parse_size(size).unwrap_err()).contains("too large");
+
+    // Exactly u64::MAX fits when given as a whole number, but not as a
+    // fraction, f64 rounds it up to 2^64
+    assert_eq!(parse_size("18446744073709551615").unwrap(), u64::MAX);
+    assert_eq!(parse_size("18446744073709551615B").unwrap(), u64::MAX);
+    assert!(too_large("18446744073709551615.0"));
+
+    // Whole numbers, multiplied exactly
+    assert!(too_large("18446744073709551616K"));
+    assert!(too_large("16384PiB"));
+    assert_eq!(parse_size("16383PiB").unwrap(), 16_383 << 50);
+
+    // Fractions, just past u64::MAX and far past it
+    assert!(too_large("16384.0PiB"));
+    assert!(too_large("18446744073709551.616K"));
+    assert!(too_large("99999999999999999999.5P"));
+    assert_eq!(parse_size("16383.5PiB").unwrap(), 16_383 << 50 | 1 << 49);
+}
@@ -0,0 +1,49 @@
+//! Scans saved with --save and compared with --diff.
+
+use clap::Parser;
+use fdu::cli::{Cli, TimeField};
+use fdu::config::Config;
+use fdu::snapshot::{self, Snapshot};
+use std::fs;
+use std::path::Path;
+
+/// Scan `root` and save it to `path` with the times of `time_field`
+fn save(root: &Path, path: &Path, time_field: TimeField) -> Snapshot {
+    let cli = Cli::parse_from(["fdu", root.to_str().unwrap()]);
+    let result = fdu::scan(&Config::from_cli(&cli).unwrap()).unwrap();
+    snapshot::save(path, &result, time_field).unwrap();
+    snapshot::load(path).unwrap()
+}
+
+#[test]
+fn snapshots_of_different_times_do_not_diff() {
+    let fixture = tempfile::tempdir().unwrap();
+    let root = fixture.path().join("tree");
+    fs::create_dir(&root).unwrap();
+    fs::write(root.join("file"), "data").unwrap();
+
+    let mtime = save(&root, &fixture.path().join("mtime.json"), TimeField::Mtime);
+    let atime = save(&root, &fixture.path().join("atime.json"), TimeField::Atime);
+    assert_eq!(mtime.time_field, "mtime");
+    assert_eq!(atime.time_field, "atime");
+
+    assert!(snapshot::diff(&mtime, &mtime, false).unwrap().is_empty());
+    let err = snapshot::diff(&mtime, &atime, false).unwrap_err();
+    assert!(err.to_string().contains("different times"), "{err}");
+}
+
+#[test]
+fn version_1_snapshots_keep_the_modification_time() {
+    let fixture = tempfile::tempdir().unwrap();
+    let path = fixture.path().join("old.json");
+    fs::write(
+        &path,
+        r#"{"version":1,"timestamp":0,"total_files":1,"dirs_scanned":1,"files_scanned":1,"errors":0,
+        "entries":[{"path":"/r","is_dir":true,"depth":0,"blocks":8,"apparent_size":4096,"files":1,"mtime":42}]}"#,
+    )
+    .unwrap();
+
+    let old = snapshot::load(&path).unwrap();
+    assert_eq!(old.time_field, "mtime");
+    assert_eq!(old.entries[0].time, 42);
+}
@@ -0,0 +1,98 @@
+//! Sorting and --top over a small synthetic set of entries.
+
+use clap::Parser;
+use fdu::Entry;
+use fdu::cli::Cli;
+use fdu::config::{Config, OutputConfig};
+use fdu::output::sort::{sort_entries, top_entries};
+use std::path::PathBuf;
+
+/// Output configuration resolved for `args`
+fn output_config(args: &[&str]) -> OutputConfig {
+    let cli = Cli::parse_from(["fdu"].iter().chain(args).chain(&["."]));
+    Config::from_cli(&cli).unwrap().output_config
+}
+
+fn entry(path: &str, blocks: u64, files: u64, time: i64) -> Entry {
+    Entry {
+        path: PathBuf::from(path),
+        depth: 1,
+        root: 0,
+        is_dir: true,
+        blocks,
+        apparent_size: blocks * 512,
+        files,
+        dirs: 1,
+        time,
+        ino: None,
+    }
+}
+
+/// Entries with equal sizes, counts and times, given out of order
+fn entries() -> Vec<Entry> {
+    vec![
+        entry("/r/d", 4, 5, 30),
+        entry("/r/c", 8, 1, 20),
+        entry("/r/b", 2, 5, 10),
+        entry("/r/a", 8, 3, 30),
+    ]
+}
+
+/// Paths of `entries` sorted with `args`
+fn sorted(args: &[&str]) -> Vec<String> {
+    let mut entries = entries();
+    sort_entries(&mut entries, &output_config(args));
+    entries.iter().map(|entry| entry.path.display().to_string()).collect()
+}
+
+#[test]
+fn sort_by_each_field() {
+    assert_eq!(sorted(&["--sort", "name"]), ["/r/a", "/r/b", "/r/c", "/r/d"]);
+    assert_eq!(sorted(&["--sort", "size"]), ["/r/b", "/r/d", "/r/a", "/r/c"]);
+    assert_eq!(sorted(&["--sort", "count"]), ["/r/c", "/r/a", "/r/b", "/r/d"]);
+    assert_eq!(sorted(&["--sort", "time"]), ["/r/b", "/r/c", "/r/a", "/r/d"]);
+}
+
+#[test]
+fn reverse_flips_the_field_only() {
+    assert_eq!(sorted(&["--sort", "name", "-r"]), ["/r/d", "/r/c", "/r/b", "/r/a"]);
+    // Ties stay in path order
+    assert_eq!(sorted(&["--sort", "size", "-r"]), ["/r/a", "/r/c", "/r/d", "/r/b"]);
+    assert_eq!(sorted(&["--sort", "count", "-r"]), ["/r/b", "/r/d", "/r/a", "/r/c"]);
+    assert_eq!(sorted(&["--sort", "time", "-r"]), ["/r/a", "/r/d", "/r/c", "/r/b"]);
+}
+
+#[test]
+fn default_order_keeps_each_path_together() {
+    let mut entries = entries();
+    entries.push(Entry {
+        root: 1,
+        ..entry("/a", 1, 1, 0)
+    });
+    entries[1].root = 2;
+    sort_entries(&mut entries, &output_config(&[]));
+    let paths: Vec<_> = entries.iter().map(|entry| entry.path.to_str().unwrap()).collect();
+    assert_eq!(paths, ["/r/a", "/r/b", "/r/d", "/a", "/r/c"]);
+
+    // Reversed by name, each path given still in turn
+    sort_entries(&mut entries, &output_config(&["-r"]));
+    let paths: Vec<_> = entries.iter().map(|entry| entry.path.to_str().unwrap()).collect();
+    assert_eq!(paths, ["/r/d", "/r/b", "/r/a", "/a", "/r/c"]);
+}
+
+#[test]
+fn top_keeps_the_largest_first() {
+    let top = |n| {
+        let mut entries = entries();
+        // Set directly, --top 0 is rejected on the command line
+        let config = OutputConfig {
+            top: Some(n),
+            ..output_config(&[])
+        };
+        top_entries(&mut entries, &config);
+        entries.iter().map(|entry| entry.path.display().to_string()).collect::<Vec<_>>()
+    };
+    assert!(top(0).is_empty());
+    assert_eq!(top(2), ["/r/a", "/r/c"]);
+    assert_eq!(top(10), ["/r/a", "/r/c", "/r/d", "/r/b"]);
+}
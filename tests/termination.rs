@@ -0,0 +1,52 @@
+//! Workers stop only once every directory has been walked: a deep tree
+//! walked over and over, on several threads, always adds up to the same
+//! total.
+
+use clap::Parser;
+use fdu::cli::Cli;
+use fdu::config::Config;
+use std::fs;
+use std::path::Path;
+
+const BRANCHES: usize = 8;
+const DEPTH: usize = 40;
+const WALKS: usize = 100;
+
+/// Build `BRANCHES` chains of `DEPTH` nested directories, each level with
+/// two files and a side directory holding one more. Chains keep few jobs
+/// queued at a time, so workers keep running out of work while others
+/// are about to publish more.
+fn build_fixture(root: &Path) -> u64 {
+    let mut files = 0;
+    for branch in 0..BRANCHES {
+        let mut dir = root.join(format!("branch{branch}"));
+        for level in 0..DEPTH {
+            fs::create_dir_all(dir.join("side")).unwrap();
+            fs::write(dir.join("small"), vec![b'x'; level]).unwrap();
+            fs::write(dir.join("large"), vec![b'x'; 5000 + level]).unwrap();
+            fs::write(dir.join("side/file"), "data").unwrap();
+            files += 3;
+            dir.push(format!("level{level}"));
+        }
+    }
+    files
+}
+
+#[test]
+fn repeated_walks_give_the_same_total() {
+    let fixture = tempfile::tempdir().unwrap();
+    let files = build_fixture(fixture.path());
+    let path = fixture.path().to_str().unwrap();
+
+    let mut expected = None;
+    for threads in ["2", "8", "32"] {
+        let cli = Cli::parse_from(["fdu", "-s", "-j", threads, path]);
+        let config = Config::from_cli(&cli).unwrap();
+        for walk in 0..WALKS {
+            let result = fdu::scan(&config).unwrap();
+            assert_eq!(result.total_files, files, "walk {walk} with {threads} threads");
+            let totals = (result.total_bytes, result.total_inodes);
+            assert_eq!(*expected.get_or_insert(totals), totals, "walk {walk} with {threads} threads");
+        }
+    }
+}